@@ -0,0 +1,361 @@
+use rand::seq::SliceRandom;
+
+use super::entry::Entry;
+use super::placement::shape_center;
+use super::shape::{Color, Shape, ShapeType};
+
+/// Named colors that palette colors are described by, picking the closest.
+const COLOR_NAMES: [(&str, Color); 13] = [
+    ("black", Color(0, 0, 0)),
+    ("white", Color(255, 255, 255)),
+    ("gray", Color(128, 128, 128)),
+    ("red", Color(255, 0, 0)),
+    ("green", Color(0, 255, 0)),
+    ("blue", Color(0, 0, 255)),
+    ("yellow", Color(255, 255, 0)),
+    ("cyan", Color(0, 255, 255)),
+    ("magenta", Color(255, 0, 255)),
+    ("orange", Color(255, 165, 0)),
+    ("purple", Color(128, 0, 128)),
+    ("pink", Color(255, 192, 203)),
+    ("brown", Color(139, 69, 19)),
+];
+
+const NUMBER_WORDS: [&str; 13] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    "eleven", "twelve",
+];
+
+fn number_word(n: usize) -> String {
+    NUMBER_WORDS
+        .get(n)
+        .map_or_else(|| n.to_string(), |word| word.to_string())
+}
+
+fn with_article(phrase: &str) -> String {
+    match phrase.chars().next() {
+        Some('a' | 'e' | 'i' | 'o' | 'u') => format!("an {phrase}"),
+        _ => format!("a {phrase}"),
+    }
+}
+
+/// Joins phrases as "a", "a and b" or "a, b and c".
+fn join_list(phrases: &[String]) -> String {
+    match phrases {
+        [] => String::new(),
+        [phrase] => phrase.clone(),
+        [rest @ .., last] => format!("{} and {last}", rest.join(", ")),
+    }
+}
+
+/// Where one shape is relative to another, judged by the axis their centers
+/// are furthest apart on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Relation {
+    LeftOf,
+    RightOf,
+    Above,
+    Below,
+}
+
+impl Relation {
+    pub(crate) fn between(a: &Shape, b: &Shape) -> Self {
+        let ((ax, ay), (bx, by)) = (shape_center(a), shape_center(b));
+        let (dx, dy) = (ax - bx, ay - by);
+
+        if dx.abs() >= dy.abs() {
+            if dx < 0. {
+                Relation::LeftOf
+            } else {
+                Relation::RightOf
+            }
+        } else if dy < 0. {
+            Relation::Above
+        } else {
+            Relation::Below
+        }
+    }
+
+    pub(crate) fn phrase(self) -> &'static str {
+        match self {
+            Relation::LeftOf => "left of",
+            Relation::RightOf => "right of",
+            Relation::Above => "above",
+            Relation::Below => "below",
+        }
+    }
+}
+
+/// How much of a scene a `Captioner` describes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verbosity {
+    /// Shape types and counts, e.g. "two squares and a circle".
+    Brief,
+    /// Colors too, e.g. "two red squares and a blue circle".
+    Standard,
+    /// Colors and where each shape is relative to the next one drawn, e.g.
+    /// "the red square is left of the blue circle". Shapes sharing their color
+    /// and type with another are left out of the relations, since "the red
+    /// square" would not say which one.
+    Detailed,
+}
+
+/// Describes entries in words, for captioning and text-to-image models.
+///
+/// Captions fill one of the templates, picked at random, where `{count}` is
+/// the number of shapes in words, `{objects}` lists the shapes and
+/// `{relations}` their spatial relations, empty unless `Verbosity::Detailed`.
+/// Without templates, captions are the objects followed by the relations
+/// after a semicolon, if any.
+#[derive(Debug, Clone)]
+pub struct Captioner {
+    verbosity: Verbosity,
+    templates: Vec<String>,
+    color_names: Vec<(Color, String)>,
+    shape_names: Vec<(ShapeType, String, String)>,
+}
+
+impl Captioner {
+    pub fn new() -> Self {
+        Captioner {
+            verbosity: Verbosity::Standard,
+            templates: Vec::new(),
+            color_names: Vec::new(),
+            shape_names: Vec::new(),
+        }
+    }
+
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Adds a template such as "There are {count} shapes: {objects}."
+    pub fn add_template(mut self, template: &str) -> Self {
+        self.templates.push(template.to_string());
+        self
+    }
+
+    /// Names a color exactly, instead of after the closest basic color.
+    pub fn color_name(mut self, r: u8, g: u8, b: u8, name: &str) -> Self {
+        self.color_names
+            .push((Color::new(r, g, b), name.to_string()));
+        self
+    }
+
+    /// Names a shape type, e.g. a sprite, in the singular and plural.
+    pub fn shape_name(mut self, shape_type: ShapeType, singular: &str, plural: &str) -> Self {
+        self.shape_names
+            .push((shape_type, singular.to_string(), plural.to_string()));
+        self
+    }
+
+    pub(crate) fn describe_color(&self, color: Color) -> String {
+        if let Some((_, name)) = self.color_names.iter().find(|(c, _)| *c == color) {
+            return name.clone();
+        }
+
+        let distance = |c: Color| {
+            (0..3)
+                .map(|i| (c.channel(i) - color.channel(i)).powi(2))
+                .sum::<f64>()
+        };
+        COLOR_NAMES
+            .iter()
+            .min_by(|(_, a), (_, b)| distance(*a).total_cmp(&distance(*b)))
+            .map(|(name, _)| name.to_string())
+            .expect("Color names are not empty")
+    }
+
+    /// Singular and plural nouns for a shape type.
+    pub(crate) fn describe_shape_type(&self, shape_type: ShapeType) -> (String, String) {
+        if let Some((_, singular, plural)) =
+            self.shape_names.iter().find(|(t, _, _)| *t == shape_type)
+        {
+            return (singular.clone(), plural.clone());
+        }
+
+        let singular = match shape_type {
+            ShapeType::Square => "square".to_string(),
+            ShapeType::Circle => "circle".to_string(),
+            ShapeType::Glyph(c) if c.is_ascii_digit() => format!("digit {c}"),
+            ShapeType::Glyph(c) if c.is_alphabetic() => format!("letter {c}"),
+            ShapeType::Glyph(c) => format!("glyph {c}"),
+            ShapeType::Sprite(i) => format!("sprite {i}"),
+        };
+        let plural = format!("{singular}s");
+        (singular, plural)
+    }
+
+    /// Noun phrase for a single shape without an article, e.g. "red square".
+    pub(crate) fn describe_shape(&self, shape: &Shape) -> String {
+        let (noun, _) = self.describe_shape_type(shape.shape_type);
+        match self.verbosity {
+            Verbosity::Brief => noun,
+            Verbosity::Standard | Verbosity::Detailed => {
+                format!("{} {noun}", self.describe_color(shape.color))
+            }
+        }
+    }
+
+    fn objects(&self, shapes: &[Shape]) -> String {
+        // (shape type, color, count) in order of first appearance
+        let mut groups: Vec<(ShapeType, Option<String>, usize)> = Vec::new();
+        for shape in shapes {
+            let color = match self.verbosity {
+                Verbosity::Brief => None,
+                Verbosity::Standard | Verbosity::Detailed => Some(self.describe_color(shape.color)),
+            };
+            match groups
+                .iter_mut()
+                .find(|(t, c, _)| *t == shape.shape_type && *c == color)
+            {
+                Some((_, _, count)) => *count += 1,
+                None => groups.push((shape.shape_type, color, 1)),
+            }
+        }
+
+        if groups.is_empty() {
+            return "no shapes".to_string();
+        }
+        let phrases: Vec<String> = groups
+            .into_iter()
+            .map(|(shape_type, color, count)| {
+                let (singular, plural) = self.describe_shape_type(shape_type);
+                let noun = if count == 1 { singular } else { plural };
+                let phrase = match color {
+                    Some(color) => format!("{color} {noun}"),
+                    None => noun,
+                };
+                if count == 1 {
+                    with_article(&phrase)
+                } else {
+                    format!("{} {phrase}", number_word(count))
+                }
+            })
+            .collect();
+        join_list(&phrases)
+    }
+
+    fn relations(&self, shapes: &[Shape]) -> String {
+        if self.verbosity != Verbosity::Detailed {
+            return String::new();
+        }
+
+        // shapes unique in the scene by their description, in drawing order
+        let described: Vec<String> = shapes.iter().map(|s| self.describe_shape(s)).collect();
+        let unique: Vec<(&Shape, &String)> = shapes
+            .iter()
+            .zip(&described)
+            .filter(|(_, d)| described.iter().filter(|other| other == d).count() == 1)
+            .collect();
+
+        let clauses: Vec<String> = unique
+            .windows(2)
+            .map(|pair| {
+                let ((a, a_name), (b, b_name)) = (pair[0], pair[1]);
+                format!(
+                    "the {a_name} is {} the {b_name}",
+                    Relation::between(a, b).phrase()
+                )
+            })
+            .collect();
+        join_list(&clauses)
+    }
+
+    /// Caption of an entry as it currently is.
+    pub fn caption(&self, entry: &Entry) -> String {
+        let shapes = entry.shapes();
+        let objects = self.objects(shapes);
+        let relations = self.relations(shapes);
+
+        match self.templates.choose(&mut rand::thread_rng()) {
+            Some(template) => template
+                .replace("{count}", &number_word(shapes.len()))
+                .replace("{objects}", &objects)
+                .replace("{relations}", &relations),
+            None if relations.is_empty() => objects,
+            None => format!("{objects}; {relations}"),
+        }
+    }
+}
+
+impl Default for Captioner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shapes of the given types and colors in a row, at the given x.
+    fn entry_of(shapes: &[(ShapeType, Color, f64)]) -> Entry {
+        let shapes = shapes
+            .iter()
+            .map(|&(shape_type, color, x)| {
+                let mut shape = Shape::new(&shape_type).size(0.1).position(x, 0.5);
+                shape.color = color;
+                shape
+            })
+            .collect();
+        Entry::new(shapes)
+    }
+
+    fn entry() -> Entry {
+        entry_of(&[
+            (ShapeType::Square, Color(250, 10, 10), 0.1),
+            (ShapeType::Circle, Color(0, 0, 255), 0.7),
+            (ShapeType::Square, Color(255, 0, 0), 0.4),
+        ])
+    }
+
+    #[test]
+    fn captions_follow_verbosity() {
+        let entry = entry();
+        let caption = |verbosity| Captioner::new().verbosity(verbosity).caption(&entry);
+
+        assert_eq!(caption(Verbosity::Brief), "two squares and a circle");
+        assert_eq!(
+            caption(Verbosity::Standard),
+            "two red squares and a blue circle"
+        );
+        // the red squares are ambiguous, leaving no relation to describe
+        assert_eq!(
+            caption(Verbosity::Detailed),
+            "two red squares and a blue circle"
+        );
+    }
+
+    #[test]
+    fn relations_skip_ambiguous_shapes() {
+        let entry = entry_of(&[
+            (ShapeType::Square, Color(250, 10, 10), 0.1),
+            (ShapeType::Circle, Color(0, 255, 0), 0.3),
+            (ShapeType::Square, Color(255, 0, 0), 0.4),
+            (ShapeType::Square, Color(0, 0, 255), 0.7),
+        ]);
+
+        let caption = Captioner::new()
+            .verbosity(Verbosity::Detailed)
+            .caption(&entry);
+        assert_eq!(
+            caption,
+            "two red squares, a green circle and a blue square; \
+             the green circle is left of the blue square"
+        );
+    }
+
+    #[test]
+    fn templates_fill_placeholders() {
+        let caption = Captioner::new()
+            .add_template("There are {count} shapes: {objects}.")
+            .color_name(0, 0, 255, "navy")
+            .caption(&entry());
+        assert_eq!(
+            caption,
+            "There are three shapes: two red squares and a navy circle."
+        );
+    }
+}
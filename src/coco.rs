@@ -0,0 +1,338 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use ndarray::Array2;
+use rayon::prelude::*;
+use serde::Serialize;
+
+use super::dataset::Dataset;
+use super::ndarray_to_image;
+
+/// How `CocoExporter` writes instance masks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Segmentation {
+    /// Uncompressed run-length encoding, in column-major order.
+    Rle,
+    /// Rectangles covering the mask row by row, which keeps holes.
+    Polygon,
+}
+
+#[derive(Serialize)]
+struct CocoImage {
+    id: usize,
+    file_name: String,
+    width: u16,
+    height: u16,
+}
+
+#[derive(Serialize)]
+struct CocoCategory {
+    id: usize,
+    name: String,
+    supercategory: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum CocoSegmentation {
+    Rle {
+        counts: Vec<usize>,
+        size: [usize; 2],
+    },
+    Polygon(Vec<Vec<f64>>),
+}
+
+#[derive(Serialize)]
+struct CocoAnnotation {
+    id: usize,
+    image_id: usize,
+    category_id: usize,
+    bbox: [f64; 4],
+    area: f64,
+    segmentation: CocoSegmentation,
+    iscrowd: u8,
+}
+
+#[derive(Serialize)]
+struct Coco {
+    images: Vec<CocoImage>,
+    annotations: Vec<CocoAnnotation>,
+    categories: Vec<CocoCategory>,
+}
+
+/// Column-major run lengths of `mask`, starting with a run of zeros.
+fn rle_counts(mask: &Array2<bool>) -> Vec<usize> {
+    let mut counts = Vec::new();
+    let (mut value, mut run) = (false, 0);
+    for &pixel in mask.t().iter() {
+        if pixel != value {
+            counts.push(run);
+            value = pixel;
+            run = 0;
+        }
+        run += 1;
+    }
+    counts.push(run);
+    counts
+}
+
+/// Rectangles covering `mask` exactly, as COCO polygons. Runs of pixels in a
+/// row are merged with identical runs in the rows below.
+fn mask_polygons(mask: &Array2<bool>) -> Vec<Vec<f64>> {
+    let (height, width) = mask.dim();
+    // open rectangles as (x1, x2, y1)
+    let mut open: Vec<(usize, usize, usize)> = Vec::new();
+    let mut polygons = Vec::new();
+    let mut close = |(x1, x2, y1): (usize, usize, usize), y2: usize| {
+        let (x1, x2, y1, y2) = (x1 as f64, x2 as f64, y1 as f64, y2 as f64);
+        polygons.push(vec![x1, y1, x2, y1, x2, y2, x1, y2]);
+    };
+
+    for y in 0..=height {
+        let mut runs = Vec::new();
+        if y < height {
+            let mut x = 0;
+            while x < width {
+                if mask[[y, x]] {
+                    let start = x;
+                    while x < width && mask[[y, x]] {
+                        x += 1;
+                    }
+                    runs.push((start, x));
+                } else {
+                    x += 1;
+                }
+            }
+        }
+
+        let mut next = Vec::with_capacity(runs.len());
+        for rectangle in open.drain(..) {
+            if let Some(i) = runs
+                .iter()
+                .position(|&(x1, x2)| (x1, x2) == (rectangle.0, rectangle.1))
+            {
+                runs.remove(i);
+                next.push(rectangle);
+            } else {
+                close(rectangle, y);
+            }
+        }
+        next.extend(runs.into_iter().map(|(x1, x2)| (x1, x2, y)));
+        open = next;
+    }
+    polygons
+}
+
+/// Generates images from a `Dataset` as a COCO detection and segmentation
+/// dataset: PNGs in `images/` and an `annotations.json` with a category per
+/// shape type. Annotations cover the visible part of each shape, as in
+/// `Entry::render_instance_ids`, and fully hidden shapes are left out.
+#[derive(Debug)]
+pub struct CocoExporter {
+    size: u16,
+    segmentation: Segmentation,
+}
+
+impl CocoExporter {
+    pub fn new(size: u16) -> Self {
+        CocoExporter {
+            size,
+            segmentation: Segmentation::Rle,
+        }
+    }
+
+    pub fn segmentation(mut self, segmentation: Segmentation) -> Self {
+        self.segmentation = segmentation;
+        self
+    }
+
+    /// Annotation of a shape's visible `mask`, with ids left to the caller,
+    /// or `None` if it is hidden.
+    fn annotate(&self, mask: &Array2<bool>) -> Option<CocoAnnotation> {
+        let size = self.size as usize;
+        let (mut x1, mut y1, mut x2, mut y2) = (size, size, 0, 0);
+        let mut area = 0;
+        for ((y, x), _) in mask.indexed_iter().filter(|(_, &pixel)| pixel) {
+            x1 = x1.min(x);
+            y1 = y1.min(y);
+            x2 = x2.max(x + 1);
+            y2 = y2.max(y + 1);
+            area += 1;
+        }
+        if area == 0 {
+            return None;
+        }
+
+        let segmentation = match self.segmentation {
+            Segmentation::Rle => CocoSegmentation::Rle {
+                counts: rle_counts(mask),
+                size: [size, size],
+            },
+            Segmentation::Polygon => CocoSegmentation::Polygon(mask_polygons(mask)),
+        };
+        Some(CocoAnnotation {
+            id: 0,
+            image_id: 0,
+            category_id: 0,
+            bbox: [x1 as f64, y1 as f64, (x2 - x1) as f64, (y2 - y1) as f64],
+            area: area as f64,
+            segmentation,
+            iscrowd: 0,
+        })
+    }
+
+    /// Generates `num_images` images and writes them with their annotations
+    /// to `dir`, creating it if needed.
+    pub fn export(
+        &self,
+        dataset: &Dataset,
+        dir: impl AsRef<Path>,
+        num_images: usize,
+    ) -> io::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir.join("images"))?;
+
+        // one category per distinct shape type
+        let mut names: Vec<String> = Vec::new();
+        for shape_type in dataset.get_shape_types() {
+            let name = shape_type.to_string();
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        let categories = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| CocoCategory {
+                id: i + 1,
+                name: name.clone(),
+                supercategory: "shape",
+            })
+            .collect();
+
+        let images = (0..num_images)
+            .map(|id| CocoImage {
+                id: id + 1,
+                file_name: format!("{id:06}.png"),
+                width: self.size,
+                height: self.size,
+            })
+            .collect();
+
+        let annotations: Vec<Vec<CocoAnnotation>> = (0..num_images)
+            .into_par_iter()
+            .map(|image_id| {
+                let entry = dataset
+                    .try_generate_random_entry()
+                    .map_err(io::Error::other)?;
+                ndarray_to_image(entry.render_entry(self.size), self.size as u32)
+                    .save(dir.join("images").join(format!("{image_id:06}.png")))
+                    .map_err(io::Error::other)?;
+
+                let ids = entry.render_instance_ids(self.size);
+                Ok(entry
+                    .shapes()
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, shape)| {
+                        let name = shape.shape_type.to_string();
+                        let category = names
+                            .iter()
+                            .position(|n| *n == name)
+                            .expect("Shape type missing from the dataset's shape types!");
+                        let mut annotation = self.annotate(&ids.mapv(|id| id == i as i64))?;
+                        annotation.image_id = image_id + 1;
+                        annotation.category_id = category + 1;
+                        Some(annotation)
+                    })
+                    .collect())
+            })
+            .collect::<io::Result<_>>()?;
+
+        let mut annotations: Vec<CocoAnnotation> = annotations.into_iter().flatten().collect();
+        for (i, annotation) in annotations.iter_mut().enumerate() {
+            annotation.id = i + 1;
+        }
+
+        let coco = Coco {
+            images,
+            annotations,
+            categories,
+        };
+        let mut writer = BufWriter::new(File::create(dir.join("annotations.json"))?);
+        serde_json::to_writer(&mut writer, &coco)?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::arr2;
+
+    #[test]
+    fn rle_counts_are_column_major() {
+        let mask = arr2(&[[false, true], [true, true]]);
+        assert_eq!(rle_counts(&mask), vec![1, 3]);
+
+        // masks starting with a set pixel start with an empty run
+        let mask = arr2(&[[true, false, true], [true, false, false]]);
+        assert_eq!(rle_counts(&mask), vec![0, 2, 2, 1, 1]);
+    }
+
+    #[test]
+    fn polygons_cover_the_mask() {
+        let mask = arr2(&[[true, true, false], [true, true, false], [true, true, true]]);
+        assert_eq!(
+            mask_polygons(&mask),
+            vec![
+                vec![0., 0., 2., 0., 2., 2., 0., 2.],
+                vec![0., 2., 3., 2., 3., 3., 0., 3.],
+            ]
+        );
+        assert!(mask_polygons(&Array2::from_elem((2, 2), false)).is_empty());
+    }
+
+    #[test]
+    fn categories_are_distinct() {
+        let dataset = Dataset::new()
+            .shape_types(vec![
+                crate::ShapeType::Square,
+                crate::ShapeType::Circle,
+                crate::ShapeType::Square,
+            ])
+            .add_color(255, 0, 0)
+            .num_shapes(2);
+        let dir = std::env::temp_dir().join(format!("funnyshapes_coco_{}", std::process::id()));
+        CocoExporter::new(16).export(&dataset, &dir, 2).unwrap();
+
+        let file = File::open(dir.join("annotations.json")).unwrap();
+        let coco: serde_json::Value = serde_json::from_reader(file).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<&str> = coco["categories"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["square", "circle"]);
+    }
+
+    #[test]
+    fn generation_failures_are_errors() {
+        let dataset = Dataset::new()
+            .shape_types(vec![crate::ShapeType::Square])
+            .add_color(255, 0, 0)
+            .size(0.5)
+            .num_shapes(5)
+            .placement(crate::Placement::NoOverlap)
+            .max_placement_tries(10);
+        let dir =
+            std::env::temp_dir().join(format!("funnyshapes_coco_failed_{}", std::process::id()));
+
+        assert!(CocoExporter::new(16).export(&dataset, &dir, 2).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,17 +1,81 @@
+use super::caption::Captioner;
+use super::distribution::Distribution;
 use super::entry::Entry;
-use super::shape::{Color, ShapeType};
+use super::obstacle::{Obstacle, ObstacleType};
+use super::physics::{Boundary, Physics};
+use super::placement::{Placement, PlacementError};
+use super::scene_graph::SceneGraph;
+use super::shape::{
+    Anchor, Color, FillStyle, ShapeOverrides, ShapeType, Texture, VelocityDistribution,
+};
+use super::split::{Split, SplitError};
+use super::sprite::Sprite;
+use super::tracking::TrackAnnotation;
+use super::vqa::{Question, QuestionGenerator};
 use super::RangeOrSingle;
-use ndarray::{s, Array3, Array4, Axis};
+use ndarray::{Array3, Array4};
+use std::sync::Arc;
 
+use rand::seq::SliceRandom;
 use rand::Rng;
+use std::error::Error;
+use std::fmt;
+
+/// Returned when a random entry could not be generated as configured.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GenerationError {
+    /// The shapes could not be placed as required by the placement policy.
+    Placement(PlacementError),
+    /// No shape allowed in the split's mode was drawn.
+    Split(SplitError),
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenerationError::Placement(error) => error.fmt(f),
+            GenerationError::Split(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for GenerationError {}
+
+impl From<PlacementError> for GenerationError {
+    fn from(error: PlacementError) -> Self {
+        GenerationError::Placement(error)
+    }
+}
+
+impl From<SplitError> for GenerationError {
+    fn from(error: SplitError) -> Self {
+        GenerationError::Split(error)
+    }
+}
 #[derive(Debug)]
 pub struct Dataset {
     shape_types: Vec<ShapeType>,
     color_palette: Vec<Color>,
-    size_range: RangeOrSingle<f64>,
-    position_range: RangeOrSingle<f64>,
-    velocity_range: RangeOrSingle<f64>,
-    num_shapes_range: RangeOrSingle<usize>,
+    fill_styles: Vec<FillStyle>,
+    textures: Vec<Texture>,
+    size_distribution: Distribution,
+    position_distribution: Distribution,
+    anchor: Anchor,
+    velocity_distribution: VelocityDistribution,
+    num_shapes_distribution: Distribution,
+    mass_distribution: Distribution,
+    overrides: Vec<(ShapeType, ShapeOverrides)>,
+    split: Split,
+    physics: Physics,
+    boundary: Boundary,
+    obstacles: Vec<Obstacle>,
+    obstacle_types: Vec<ObstacleType>,
+    obstacle_size_range: RangeOrSingle<f64>,
+    obstacle_color: Color,
+    num_obstacles_range: RangeOrSingle<usize>,
+    sprites: Arc<Vec<Sprite>>,
+    placement: Placement,
+    max_placement_tries: usize,
     // TODO: background color palette
     // TODO: weightings for picking shapes / colors
 }
@@ -21,14 +85,35 @@ impl Dataset {
         Self {
             shape_types: Vec::new(),
             color_palette: Vec::new(),
-            size_range: RangeOrSingle::Single(1.0),
-            position_range: RangeOrSingle::Range(0.0, 1.0),
-            velocity_range: RangeOrSingle::Range(-0.5, 0.5),
-            num_shapes_range: RangeOrSingle::Single(3),
+            fill_styles: vec![FillStyle::Filled],
+            textures: vec![Texture::Solid],
+            size_distribution: Distribution::constant(1.0),
+            position_distribution: Distribution::uniform(0.0, 1.0),
+            anchor: Anchor::TopLeft,
+            velocity_distribution: VelocityDistribution::Cartesian(Distribution::uniform(
+                -0.5, 0.5,
+            )),
+            num_shapes_distribution: Distribution::constant(3.0),
+            mass_distribution: Distribution::constant(1.0),
+            overrides: Vec::new(),
+            split: Split::new(),
+            physics: Physics::new(),
+            boundary: Boundary::Reflect,
+            obstacles: Vec::new(),
+            obstacle_types: vec![ObstacleType::Rectangle],
+            obstacle_size_range: RangeOrSingle::Range(0.05, 0.3),
+            obstacle_color: Color::new(0x80, 0x80, 0x80),
+            num_obstacles_range: RangeOrSingle::Single(0),
+            sprites: Arc::new(Vec::new()),
+            placement: Placement::Independent,
+            max_placement_tries: 1000,
         }
     }
 
+    /// Shape types to pick from. Sprites must be added before the shape types
+    /// referring to them.
     pub fn shape_types(mut self, shape_types: Vec<ShapeType>) -> Self {
+        shape_types.iter().for_each(|t| self.check_shape_type(*t));
         self.shape_types = shape_types;
         self
     }
@@ -38,62 +123,342 @@ impl Dataset {
         self
     }
 
+    pub fn fill_styles(mut self, fill_styles: Vec<FillStyle>) -> Self {
+        self.fill_styles = fill_styles;
+        self
+    }
+
+    /// Textures mix each shape's color with a second color, also drawn from
+    /// the palette.
+    pub fn textures(mut self, textures: Vec<Texture>) -> Self {
+        self.textures = textures;
+        self
+    }
+
+    /// Registers a sprite, to be referenced by `ShapeType::Sprite` using the
+    /// order sprites were added in (starting at 0). Sprites have to be added
+    /// before shape types referring to them.
+    pub fn add_sprite(mut self, sprite: Sprite) -> Self {
+        Arc::get_mut(&mut self.sprites)
+            .expect("Cannot add sprites while entries still reference them")
+            .push(sprite);
+        self
+    }
+
     pub fn size_range(mut self, size_lower: f64, size_upper: f64) -> Self {
-        self.size_range = RangeOrSingle::Range(size_lower, size_upper);
+        self.size_distribution = Distribution::uniform(size_lower, size_upper);
         self
     }
 
     pub fn size(mut self, size: f64) -> Self {
-        self.size_range = RangeOrSingle::Single(size);
+        self.size_distribution = Distribution::constant(size);
+        self
+    }
+
+    pub fn size_distribution(mut self, distribution: Distribution) -> Self {
+        check_distribution(&distribution);
+        self.size_distribution = distribution;
         self
     }
 
     pub fn position_range(mut self, position_lower: f64, position_upper: f64) -> Self {
-        self.position_range = RangeOrSingle::Range(position_lower, position_upper);
+        self.position_distribution = Distribution::uniform(position_lower, position_upper);
         self
     }
 
     pub fn position(mut self, position: f64) -> Self {
-        self.position_range = RangeOrSingle::Single(position);
+        self.position_distribution = Distribution::constant(position);
+        self
+    }
+
+    /// Distribution of the anchor point on each axis. Samples that would put
+    /// a shape out of frame are redrawn, as in `Distribution::sample_within`.
+    pub fn position_distribution(mut self, distribution: Distribution) -> Self {
+        check_distribution(&distribution);
+        self.position_distribution = distribution;
+        self
+    }
+
+    /// Which point of each shape positions are drawn for. Either way, the
+    /// position range is narrowed per shape so shapes start fully in frame.
+    pub fn anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
         self
     }
 
     pub fn velocity_range(mut self, velocity_lower: f64, velocity_upper: f64) -> Self {
-        self.velocity_range = RangeOrSingle::Range(velocity_lower, velocity_upper);
+        self.velocity_distribution =
+            VelocityDistribution::Cartesian(Distribution::uniform(velocity_lower, velocity_upper));
         self
     }
 
     pub fn velocity(mut self, velocity: f64) -> Self {
-        self.velocity_range = RangeOrSingle::Single(velocity);
+        self.velocity_distribution =
+            VelocityDistribution::Cartesian(Distribution::constant(velocity));
+        self
+    }
+
+    /// Distribution of each velocity component, drawn independently.
+    pub fn velocity_distribution(mut self, distribution: Distribution) -> Self {
+        check_distribution(&distribution);
+        self.velocity_distribution = VelocityDistribution::Cartesian(distribution);
+        self
+    }
+
+    /// Draws velocities as a speed and a direction in radians, clockwise from
+    /// the x axis, instead of by component. See `VelocityDistribution::Polar`.
+    pub fn polar_velocity(mut self, speed: Distribution, direction: Distribution) -> Self {
+        check_distribution(&speed);
+        check_distribution(&direction);
+        self.velocity_distribution = VelocityDistribution::Polar { speed, direction };
+        self
+    }
+
+    pub fn mass_range(mut self, mass_lower: f64, mass_upper: f64) -> Self {
+        self.mass_distribution = Distribution::uniform(mass_lower, mass_upper);
+        self
+    }
+
+    pub fn mass(mut self, mass: f64) -> Self {
+        self.mass_distribution = Distribution::constant(mass);
+        self
+    }
+
+    pub fn mass_distribution(mut self, distribution: Distribution) -> Self {
+        check_distribution(&distribution);
+        self.mass_distribution = distribution;
+        self
+    }
+
+    /// Restricts shapes to one part of a compositional hold-out split, e.g.
+    /// `Split::mode(SplitMode::Test)` for only held out test combinations.
+    pub fn split(mut self, split: Split) -> Self {
+        self.split = split;
+        self
+    }
+
+    /// Replaces the palette, size, velocity or mass distributions for shapes
+    /// of `shape_type`, e.g. to make circles small and fast. Replaces any
+    /// earlier overrides for the same type.
+    pub fn override_shape_type(mut self, shape_type: ShapeType, overrides: ShapeOverrides) -> Self {
+        self.check_shape_type(shape_type);
+        [&overrides.size, &overrides.mass]
+            .into_iter()
+            .flatten()
+            .for_each(check_distribution);
+        if let Some(Err(error)) = overrides.velocity.as_ref().map(|v| v.validate()) {
+            panic!("Invalid velocity override for {shape_type}: {error}");
+        }
+        if overrides
+            .color_palette
+            .as_ref()
+            .is_some_and(|palette| palette.is_empty())
+        {
+            panic!("Overridden color palette for {shape_type} is empty!");
+        }
+
+        self.overrides.retain(|(t, _)| *t != shape_type);
+        self.overrides.push((shape_type, overrides));
+        self
+    }
+
+    pub fn gravity(mut self, x: f64, y: f64) -> Self {
+        self.physics = self.physics.gravity(x, y);
+        self
+    }
+
+    pub fn drag(mut self, drag: f64) -> Self {
+        self.physics = self.physics.drag(drag);
+        self
+    }
+
+    pub fn restitution(mut self, restitution: f64) -> Self {
+        self.physics = self.physics.restitution(restitution);
+        self
+    }
+
+    pub fn boundary(mut self, boundary: Boundary) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    /// Adds an obstacle present in every entry, on top of any random ones.
+    pub fn add_obstacle(mut self, obstacle: Obstacle) -> Self {
+        self.obstacles.push(obstacle);
+        self
+    }
+
+    pub fn obstacle_types(mut self, obstacle_types: Vec<ObstacleType>) -> Self {
+        self.obstacle_types = obstacle_types;
+        self
+    }
+
+    pub fn obstacle_size_range(mut self, size_lower: f64, size_upper: f64) -> Self {
+        if size_lower <= 0.0 || size_upper < size_lower || size_upper > 1.0 {
+            panic!(
+                "Obstacle sizes must be ordered and in (0, 1]. Got {size_lower} to {size_upper}"
+            );
+        }
+        self.obstacle_size_range = RangeOrSingle::Range(size_lower, size_upper);
+        self
+    }
+
+    pub fn obstacle_color(mut self, r: u8, g: u8, b: u8) -> Self {
+        self.obstacle_color = Color::new(r, g, b);
+        self
+    }
+
+    pub fn num_obstacles_range(
+        mut self,
+        num_obstacles_lower: usize,
+        num_obstacles_upper: usize,
+    ) -> Self {
+        self.num_obstacles_range = RangeOrSingle::Range(num_obstacles_lower, num_obstacles_upper);
+        self
+    }
+
+    pub fn num_obstacles(mut self, num_obstacles: usize) -> Self {
+        self.num_obstacles_range = RangeOrSingle::Single(num_obstacles);
+        self
+    }
+
+    pub fn placement(mut self, placement: Placement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    /// Tries at placing each shape, or each scene for
+    /// `Placement::MinVisibility`, before giving up on an entry.
+    pub fn max_placement_tries(mut self, max_placement_tries: usize) -> Self {
+        self.max_placement_tries = max_placement_tries;
         self
     }
 
     pub fn num_shapes_range(mut self, num_shapes_lower: usize, num_shapes_upper: usize) -> Self {
-        self.num_shapes_range = RangeOrSingle::Range(num_shapes_lower, num_shapes_upper);
+        self.num_shapes_distribution =
+            Distribution::uniform(num_shapes_lower as f64, num_shapes_upper as f64);
         self
     }
 
     pub fn num_shapes(mut self, num_shapes: usize) -> Self {
-        self.num_shapes_range = RangeOrSingle::Single(num_shapes);
+        self.num_shapes_distribution = Distribution::constant(num_shapes as f64);
         self
     }
+
+    /// Distribution of the number of shapes, rounded to whole numbers.
+    pub fn num_shapes_distribution(mut self, distribution: Distribution) -> Self {
+        check_distribution(&distribution);
+        self.num_shapes_distribution = distribution;
+        self
+    }
+
+    fn check_shape_type(&self, shape_type: ShapeType) {
+        if let ShapeType::Sprite(i) = shape_type {
+            if i >= self.sprites.len() {
+                panic!(
+                    "No sprite {i}, only {} added! Add sprites before shape types using them.",
+                    self.sprites.len()
+                );
+            }
+        }
+    }
+}
+
+fn check_distribution(distribution: &Distribution) {
+    if let Err(error) = distribution.validate() {
+        panic!("Invalid distribution {distribution:?}: {error}");
+    }
+}
+
+impl Default for Dataset {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Dataset {
-    pub fn generate_random_entry(&self) -> Entry {
-        let num_shapes: usize = match self.num_shapes_range {
-            RangeOrSingle::Range(l, u) => rand::thread_rng().gen_range(l..=u),
+    /// Shape types entries are drawn from.
+    pub fn get_shape_types(&self) -> &[ShapeType] {
+        &self.shape_types
+    }
+
+    pub fn get_sprites(&self) -> &[Sprite] {
+        &self.sprites
+    }
+
+    /// Colors shapes can have, from the palette and then from overrides.
+    pub fn get_colors(&self) -> Vec<Color> {
+        let mut colors = self.color_palette.clone();
+        for (_, overrides) in &self.overrides {
+            for &color in overrides.color_palette.iter().flatten() {
+                if !colors.contains(&color) {
+                    colors.push(color);
+                }
+            }
+        }
+        colors
+    }
+
+    fn generate_random_obstacles(&self) -> Vec<Obstacle> {
+        let mut rng = rand::thread_rng();
+        let num_obstacles: usize = match self.num_obstacles_range {
+            RangeOrSingle::Range(l, u) => rng.gen_range(l..=u),
             RangeOrSingle::Single(v) => v,
         };
 
-        Entry::new_from_random(
+        let mut obstacles = self.obstacles.clone();
+        for _ in 0..num_obstacles {
+            let obstacle_type = self
+                .obstacle_types
+                .choose(&mut rng)
+                .expect("Failed picking random obstacle from vec of possible obstacles!");
+            let mut obstacle = Obstacle::new_random(*obstacle_type, &self.obstacle_size_range);
+            obstacle.color = self.obstacle_color;
+            obstacles.push(obstacle);
+        }
+        obstacles
+    }
+
+    /// A random entry, or an error if its shapes could not be placed as
+    /// required by the placement policy or none are allowed by the split.
+    pub fn try_generate_random_entry(&self) -> Result<Entry, GenerationError> {
+        let num_shapes = self
+            .num_shapes_distribution
+            .sample_count(&mut rand::thread_rng());
+
+        let mut entry = Entry::new_from_random(
             num_shapes,
             &self.shape_types,
             &self.color_palette,
-            &self.size_range,
-            &self.position_range,
-            &self.velocity_range,
-        )
+            &self.fill_styles,
+            &self.textures,
+            &self.size_distribution,
+            &self.position_distribution,
+            self.anchor,
+            &self.velocity_distribution,
+            &self.mass_distribution,
+            &self.overrides,
+            &self.split,
+            &self.sprites,
+        )?
+        .physics(self.physics)
+        .boundary(self.boundary)
+        .obstacles(self.generate_random_obstacles());
+        entry.place_shapes(
+            &self.position_distribution,
+            self.anchor,
+            self.placement,
+            self.max_placement_tries,
+        )?;
+
+        Ok(entry)
+    }
+
+    /// A random entry. Panics if its shapes could not be placed as required by
+    /// the placement policy or none are allowed by the split.
+    pub fn generate_random_entry(&self) -> Entry {
+        self.try_generate_random_entry()
+            .unwrap_or_else(|error| panic!("{error}"))
     }
 
     pub fn get_random_image_array(&self, size: u16) -> Array3<f64> {
@@ -101,6 +466,43 @@ impl Dataset {
         entry.render_entry(size)
     }
 
+    /// A random image with its caption from `captioner`.
+    pub fn get_random_image_with_caption(
+        &self,
+        size: u16,
+        captioner: &Captioner,
+    ) -> (Array3<f64>, String) {
+        let entry = self.generate_random_entry();
+        (entry.render_entry(size), captioner.caption(&entry))
+    }
+
+    /// A random image with up to `num_questions` questions about it from
+    /// `generator`.
+    pub fn get_random_image_with_questions(
+        &self,
+        size: u16,
+        generator: &QuestionGenerator,
+        num_questions: usize,
+    ) -> (Array3<f64>, Vec<Question>) {
+        let entry = self.generate_random_entry();
+        let questions = generator.generate(self, &entry, num_questions);
+        (entry.render_entry(size), questions)
+    }
+
+    /// A random image with its scene graph, as in `SceneGraph::new`.
+    pub fn get_random_image_with_scene_graph(
+        &self,
+        size: u16,
+        touch_distance: f64,
+        names: &Captioner,
+    ) -> (Array3<f64>, SceneGraph) {
+        let entry = self.generate_random_entry();
+        (
+            entry.render_entry(size),
+            SceneGraph::new(&entry, touch_distance, names),
+        )
+    }
+
     pub fn get_random_video_array(
         &self,
         num_frames: usize,
@@ -108,18 +510,33 @@ impl Dataset {
         step_size: f64,
     ) -> Array4<f64> {
         let mut entry = self.generate_random_entry();
+        entry.render_video(num_frames, size, step_size)
+    }
 
-        let mut video_array = Array4::zeros((num_frames as usize, 3, size as usize, size as usize));
-        for i in 0..num_frames {
-            let array = entry.render_entry(size);
-            entry.step_entry(step_size);
-
-            video_array
-                .slice_mut(s![i..i + 1, .., .., ..])
-                .assign(&array);
-        }
+    /// A random video with the optical flow between consecutive frames, as
+    /// `(frames, forward_flow, backward_flow)`. Forward flow `i` is from frame
+    /// `i` to frame `i + 1`, and backward flow `i` from frame `i + 1` back to
+    /// frame `i`, both in pixels with (dx, dy) channels.
+    pub fn get_random_video_with_flow_arrays(
+        &self,
+        num_frames: usize,
+        size: u16,
+        step_size: f64,
+    ) -> (Array4<f64>, Array4<f64>, Array4<f64>) {
+        self.generate_random_entry()
+            .render_video_with_flow(num_frames, size, step_size)
+    }
 
-        video_array
+    /// A random video with tracking annotations for every shape in every
+    /// frame, ordered by frame and then by id.
+    pub fn get_random_video_with_tracks(
+        &self,
+        num_frames: usize,
+        size: u16,
+        step_size: f64,
+    ) -> (Array4<f64>, Vec<TrackAnnotation>) {
+        self.generate_random_entry()
+            .render_video_with_tracks(num_frames, size, step_size)
     }
 }
 
@@ -0,0 +1,266 @@
+use rand::distributions::WeightedIndex;
+use rand::prelude::Distribution as _;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::RangeOrSingle;
+
+/// Tries at drawing from a distribution inside some bounds before clamping.
+const MAX_REJECTIONS: usize = 100;
+
+/// A distribution of scalar parameters, such as shape sizes or counts. In
+/// configs it is written with its variant as `type`, e.g.
+/// `{"type": "normal", "mean": 0.1, "std": 0.02}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Distribution {
+    Constant {
+        value: f64,
+    },
+    Uniform {
+        low: f64,
+        high: f64,
+    },
+    Normal {
+        mean: f64,
+        std: f64,
+    },
+    /// A normal distribution restricted to [low, high].
+    TruncatedNormal {
+        mean: f64,
+        std: f64,
+        low: f64,
+        high: f64,
+    },
+    /// Uniform in log space, for scale parameters. Both bounds must be
+    /// positive.
+    LogUniform {
+        low: f64,
+        high: f64,
+    },
+    /// One of `values`, picked with probability proportional to `weights`, or
+    /// uniformly if there are no weights.
+    Choice {
+        values: Vec<f64>,
+        #[serde(default)]
+        weights: Vec<f64>,
+    },
+    /// A sample from one of `components`, picked as in `Choice`.
+    Mixture {
+        components: Vec<Distribution>,
+        #[serde(default)]
+        weights: Vec<f64>,
+    },
+}
+
+/// Index picked with probability proportional to `weights`, or uniformly
+/// from `len` if there are none.
+fn weighted_index<R: Rng + ?Sized>(rng: &mut R, weights: &[f64], len: usize) -> usize {
+    if weights.is_empty() {
+        rng.gen_range(0..len)
+    } else {
+        WeightedIndex::new(weights)
+            .expect("Invalid weights!")
+            .sample(rng)
+    }
+}
+
+/// A standard normal sample, using the Box-Muller transform.
+fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    let u: f64 = 1. - rng.gen::<f64>();
+    let v: f64 = rng.gen();
+    (-2. * u.ln()).sqrt() * (2. * std::f64::consts::PI * v).cos()
+}
+
+impl Distribution {
+    pub fn constant(value: f64) -> Self {
+        Distribution::Constant { value }
+    }
+
+    pub fn uniform(low: f64, high: f64) -> Self {
+        Distribution::Uniform { low, high }
+    }
+
+    /// Checks the parameters make sense, describing the first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        let check_weights = |weights: &[f64], len: usize| {
+            if len == 0 {
+                Err("Choices and mixtures need at least one option".to_string())
+            } else if !weights.is_empty() && weights.len() != len {
+                Err(format!("Expected {len} weights, got {}", weights.len()))
+            } else if !weights.is_empty() && WeightedIndex::new(weights).is_err() {
+                Err(format!("Invalid weights {weights:?}"))
+            } else {
+                Ok(())
+            }
+        };
+
+        match self {
+            Distribution::Constant { .. } => Ok(()),
+            Distribution::Uniform { low, high } if low > high => {
+                Err(format!("Uniform low {low} is above high {high}"))
+            }
+            Distribution::Uniform { .. } => Ok(()),
+            Distribution::Normal { std, .. } | Distribution::TruncatedNormal { std, .. }
+                if *std < 0. =>
+            {
+                Err(format!(
+                    "Standard deviation must be non-negative. Got {std}"
+                ))
+            }
+            Distribution::Normal { .. } => Ok(()),
+            Distribution::TruncatedNormal { low, high, .. } if low > high => {
+                Err(format!("Truncated normal low {low} is above high {high}"))
+            }
+            Distribution::TruncatedNormal { .. } => Ok(()),
+            Distribution::LogUniform { low, high } if *low <= 0. || low > high => Err(format!(
+                "Log-uniform bounds must be positive and ordered. Got {low}, {high}"
+            )),
+            Distribution::LogUniform { .. } => Ok(()),
+            Distribution::Choice { values, weights } => check_weights(weights, values.len()),
+            Distribution::Mixture {
+                components,
+                weights,
+            } => {
+                check_weights(weights, components.len())?;
+                components.iter().try_for_each(Distribution::validate)
+            }
+        }
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match self {
+            Distribution::Constant { value } => *value,
+            Distribution::Uniform { low, high } => rng.gen_range(*low..=*high),
+            Distribution::Normal { mean, std } => mean + std * standard_normal(rng),
+            Distribution::TruncatedNormal {
+                mean,
+                std,
+                low,
+                high,
+            } => Distribution::Normal {
+                mean: *mean,
+                std: *std,
+            }
+            .sample_within(rng, *low, *high),
+            Distribution::LogUniform { low, high } => rng.gen_range(low.ln()..=high.ln()).exp(),
+            Distribution::Choice { values, weights } => {
+                values[weighted_index(rng, weights, values.len())]
+            }
+            Distribution::Mixture {
+                components,
+                weights,
+            } => components[weighted_index(rng, weights, components.len())].sample(rng),
+        }
+    }
+
+    /// A sample restricted to [min, max]. Uniform and constant distributions
+    /// are narrowed to the bounds, falling back to the nearest bound if they
+    /// miss them entirely, and others are redrawn until inside, or clamped
+    /// after enough tries.
+    pub fn sample_within<R: Rng + ?Sized>(&self, rng: &mut R, min: f64, max: f64) -> f64 {
+        match *self {
+            Distribution::Constant { value } => value.clamp(min, max),
+            Distribution::Uniform { low, high } => {
+                let (low, high) = (low.max(min), high.min(max));
+                if low <= high {
+                    rng.gen_range(low..=high)
+                } else {
+                    low.clamp(min, max)
+                }
+            }
+            _ => {
+                let mut value = self.sample(rng);
+                for _ in 0..MAX_REJECTIONS {
+                    if (min..=max).contains(&value) {
+                        break;
+                    }
+                    value = self.sample(rng);
+                }
+                value.clamp(min, max)
+            }
+        }
+    }
+
+    /// A sample rounded to a count, with negative values counting as 0.
+    /// Uniform distributions pick each whole number in their range with
+    /// equal probability.
+    pub fn sample_count<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        let count = match *self {
+            Distribution::Uniform { low, high } if low.ceil() <= high.floor() => {
+                rng.gen_range(low.ceil() as i64..=high.floor() as i64) as f64
+            }
+            _ => self.sample(rng).round(),
+        };
+        count.max(0.) as usize
+    }
+}
+
+impl From<RangeOrSingle<f64>> for Distribution {
+    fn from(range_or_single: RangeOrSingle<f64>) -> Self {
+        match range_or_single {
+            RangeOrSingle::Range(low, high) => Distribution::uniform(low, high),
+            RangeOrSingle::Single(value) => Distribution::constant(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_configs() {
+        let distribution: Distribution = serde_json::from_str(
+            r#"{"type": "mixture", "components": [
+                {"type": "normal", "mean": 0.1, "std": 0.02},
+                {"type": "choice", "values": [0.3, 0.4], "weights": [1, 3]}
+            ], "weights": [0.5, 0.5]}"#,
+        )
+        .unwrap();
+        assert!(distribution.validate().is_ok());
+
+        let invalid = Distribution::LogUniform {
+            low: 0.0,
+            high: 1.0,
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn truncated_normal_resamples_instead_of_clamping() {
+        let mut rng = rand::thread_rng();
+        let distribution = Distribution::TruncatedNormal {
+            mean: 0.5,
+            std: 1.0,
+            low: 0.0,
+            high: 1.0,
+        };
+
+        let samples: Vec<f64> = (0..10_000).map(|_| distribution.sample(&mut rng)).collect();
+        assert!(samples.iter().all(|s| (0.0..=1.0).contains(s)));
+        // clamping would pile about a third of the samples on each bound
+        assert!(samples.iter().all(|&s| s != 0.0 && s != 1.0));
+
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        assert!((mean - 0.5).abs() < 0.02, "mean {mean}");
+        // nearly flat over [0, 1], so the variance is close to 1/12
+        let variance =
+            samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+        assert!((0.07..0.09).contains(&variance), "variance {variance}");
+    }
+
+    #[test]
+    fn uniform_counts_cover_the_range() {
+        let mut rng = rand::thread_rng();
+        let distribution = Distribution::uniform(3.0, 5.0);
+
+        let mut seen = [false; 3];
+        for _ in 0..1000 {
+            let count = distribution.sample_count(&mut rng);
+            assert!((3..=5).contains(&count));
+            seen[count - 3] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+    }
+}
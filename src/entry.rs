@@ -5,138 +5,878 @@ use rand::seq::SliceRandom;
 // use rayon::iter::IntoParallelRefMutIterator;
 // use rayon::iter::ParallelIterator
 
-use super::shape::{Color, NewRandom1, NewRandom2, Position, Shape, ShapeType, Size, Velocity};
-use super::RangeOrSingle;
+use super::distribution::Distribution;
+use super::font;
+use super::obstacle::Obstacle;
+use super::physics::{Boundary, Physics};
+use super::placement::{
+    overlap_area, overlaps_obstacle, shape_box, BoundingBox, Placement, PlacementError,
+};
+use super::shape::{
+    Anchor, Color, FillStyle, Mass, NewRandom1, Position, Shape, ShapeOverrides, ShapeType, Size,
+    Texture, VelocityDistribution,
+};
+use super::split::{Split, SplitError};
+use super::sprite::Sprite;
+use super::tracking::TrackAnnotation;
 
-use ndarray::{array, Array1, Array2, Array3, Axis, AxisDescription, Slice, Zip};
+use std::sync::Arc;
+
+use ndarray::{s, Array2, Array3, Array4, Zip};
+
+/// Resolution `Placement::MinVisibility` is checked at.
+const VISIBILITY_CHECK_SIZE: u16 = 64;
+
+/// Draws of a shape's type, color and size before giving up on finding one
+/// the split allows.
+const MAX_SPLIT_TRIES: usize = 10_000;
 
 #[derive(Debug)]
 pub struct Entry {
     shapes: Vec<Shape>,
+    sprites: Arc<Vec<Sprite>>,
+    physics: Physics,
+    boundary: Boundary,
+    obstacles: Vec<Obstacle>,
 }
 
 impl Entry {
-    fn new_empty_with_capacity(capacity: usize) -> Self {
+    /// An entry with the given shapes, using no sprites.
+    pub fn new(shapes: Vec<Shape>) -> Self {
+        let mut entry = Self::new_empty_with_capacity(0, &Arc::new(Vec::new()));
+        entry.shapes = shapes;
+        entry
+    }
+
+    fn new_empty_with_capacity(capacity: usize, sprites: &Arc<Vec<Sprite>>) -> Self {
         Entry {
             shapes: Vec::with_capacity(capacity),
+            sprites: Arc::clone(sprites),
+            physics: Physics::new(),
+            boundary: Boundary::Reflect,
+            obstacles: Vec::new(),
         }
     }
 
+    /// A random entry, or an error if no shape the split allows was drawn
+    /// within `MAX_SPLIT_TRIES` tries.
+    #[allow(clippy::too_many_arguments)]
     pub fn new_from_random(
         num_shapes: usize,
-        shape_types: &Vec<ShapeType>,
-        color_palette: &Vec<Color>,
-        size_range: &RangeOrSingle<f64>,
-        position_range: &RangeOrSingle<f64>,
-        velocity_range: &RangeOrSingle<f64>,
-    ) -> Self {
-        let mut entry = Self::new_empty_with_capacity(num_shapes);
+        shape_types: &[ShapeType],
+        color_palette: &[Color],
+        fill_styles: &[FillStyle],
+        textures: &[Texture],
+        size_distribution: &Distribution,
+        position_distribution: &Distribution,
+        anchor: Anchor,
+        velocity_distribution: &VelocityDistribution,
+        mass_distribution: &Distribution,
+        overrides: &[(ShapeType, ShapeOverrides)],
+        split: &Split,
+        sprites: &Arc<Vec<Sprite>>,
+    ) -> Result<Self, SplitError> {
+        let mut entry = Self::new_empty_with_capacity(num_shapes, sprites);
 
         let mut rng = rand::thread_rng();
         for _ in 0..num_shapes {
-            let mut shape = Shape::new(
-                shape_types
+            // redraw the attributes a split holds out until they are allowed
+            let mut tries = 0;
+            let (shape_type, overrides, color, size) = loop {
+                let shape_type = shape_types
                     .choose(&mut rng)
-                    .expect("Failed picking random shape from vec of possible shapes!"),
-            );
-            shape.color = Color::new_random_from_palette(&color_palette);
-            shape.size = Size::new_from_range_or_single(size_range);
-            shape.position = Position::new_from_range_or_single(position_range);
-            shape.velocity = Velocity::new_from_range_or_single(velocity_range);
+                    .expect("Failed picking random shape from vec of possible shapes!");
+                let overrides = overrides
+                    .iter()
+                    .find(|(t, _)| t == shape_type)
+                    .map(|(_, o)| o);
+                let color = Color::new_random_from_palette(
+                    overrides
+                        .and_then(|o| o.color_palette.as_deref())
+                        .unwrap_or(color_palette),
+                );
+                let size = overrides
+                    .and_then(|o| o.size.as_ref())
+                    .unwrap_or(size_distribution)
+                    .sample_within(&mut rng, f64::MIN_POSITIVE, 1.0);
+
+                if split.accepts(*shape_type, color, size) {
+                    break (shape_type, overrides, color, size);
+                }
+                tries += 1;
+                if tries >= MAX_SPLIT_TRIES {
+                    return Err(SplitError {
+                        mode: split.get_mode(),
+                        tries,
+                    });
+                }
+            };
+
+            let mut shape = Shape::new(shape_type);
+            let color_palette = overrides
+                .and_then(|o| o.color_palette.as_deref())
+                .unwrap_or(color_palette);
+            let velocity_distribution = overrides
+                .and_then(|o| o.velocity.as_ref())
+                .unwrap_or(velocity_distribution);
+            let mass_distribution = overrides
+                .and_then(|o| o.mass.as_ref())
+                .unwrap_or(mass_distribution);
+
+            shape.color = color;
+            // keep textures visible by mixing with a different color when possible
+            let others: Vec<Color> = color_palette
+                .iter()
+                .copied()
+                .filter(|c| *c != shape.color)
+                .collect();
+            shape.secondary_color = if others.is_empty() {
+                shape.color
+            } else {
+                Color::new_random_from_palette(&others)
+            };
+            shape.texture = *textures
+                .choose(&mut rng)
+                .expect("Failed picking random texture from vec of possible textures!");
+            shape.fill_style = *fill_styles
+                .choose(&mut rng)
+                .expect("Failed picking random fill style from vec of possible fill styles!");
+            shape.size = Size(size);
+            shape.position = Position::new_in_frame(position_distribution, anchor, shape.size.0);
+            shape.velocity = velocity_distribution.sample(&mut rng);
+            shape.mass =
+                Mass::new(mass_distribution.sample_within(&mut rng, f64::MIN_POSITIVE, f64::MAX));
 
             entry.shapes.push(shape);
         }
 
-        entry
+        Ok(entry)
+    }
+
+    /// Moves the shapes so that none overlaps an obstacle and they satisfy
+    /// `placement`, keeping the positions they have where possible and
+    /// otherwise redrawing them from `position_distribution`, as in
+    /// `Position::new_in_frame`. Shapes are placed in drawing order with up to
+    /// `max_tries` tries each, counting their current position, except for
+    /// `Placement::MinVisibility`, which tries up to `max_tries` whole scenes.
+    pub fn place_shapes(
+        &mut self,
+        position_distribution: &Distribution,
+        anchor: Anchor,
+        placement: Placement,
+        max_tries: usize,
+    ) -> Result<(), PlacementError> {
+        let error = PlacementError {
+            placement,
+            tries: max_tries,
+        };
+        let obstacles = &self.obstacles;
+        let clear = |candidate: BoundingBox| {
+            !obstacles
+                .iter()
+                .any(|obstacle| overlaps_obstacle(candidate, obstacle))
+        };
+
+        match placement {
+            Placement::MinVisibility(fraction) => {
+                for tries in 0..max_tries {
+                    if tries > 0 {
+                        for shape in self.shapes.iter_mut() {
+                            shape.position =
+                                Position::new_in_frame(position_distribution, anchor, shape.size.0);
+                        }
+                    }
+                    if self.shapes.iter().all(|shape| clear(shape_box(shape)))
+                        && self
+                            .track_annotations(0, VISIBILITY_CHECK_SIZE)
+                            .iter()
+                            .all(|track| track.visibility >= fraction)
+                    {
+                        return Ok(());
+                    }
+                }
+                Err(error)
+            }
+            Placement::Independent
+            | Placement::NoOverlap
+            | Placement::MinSeparation(_)
+            | Placement::MaxOcclusion(_) => {
+                let mut placed = Vec::with_capacity(self.shapes.len());
+                // area of each placed box covered by the ones placed after it
+                let mut occluded = Vec::with_capacity(self.shapes.len());
+
+                for shape in self.shapes.iter_mut() {
+                    let size = shape.size.0;
+                    let bounds = |p: Position| (p.0, p.1, p.0 + size, p.1 + size);
+
+                    let redrawn = (1..max_tries)
+                        .map(|_| Position::new_in_frame(position_distribution, anchor, size));
+                    let position = std::iter::once(shape.position)
+                        .chain(redrawn)
+                        .find(|&p| {
+                            clear(bounds(p)) && placement.accepts(bounds(p), &placed, &occluded)
+                        })
+                        .ok_or_else(|| error.clone())?;
+                    shape.position = position;
+
+                    let candidate = bounds(position);
+                    for (&other, covered) in placed.iter().zip(occluded.iter_mut()) {
+                        *covered += overlap_area(candidate, other);
+                    }
+                    placed.push(candidate);
+                    occluded.push(0.);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn shapes(&self) -> &[Shape] {
+        &self.shapes
     }
 
-    fn render_square(image: &mut Array3<f64>, shape: &Shape, size: usize) {
-        let float_to_coord = |f: f64| (f * (size as f64)) as isize;
+    pub fn shapes_mut(&mut self) -> &mut [Shape] {
+        &mut self.shapes
+    }
+
+    pub fn physics(mut self, physics: Physics) -> Self {
+        self.physics = physics;
+        self
+    }
+
+    pub fn boundary(mut self, boundary: Boundary) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    pub fn obstacles(mut self, obstacles: Vec<Obstacle>) -> Self {
+        self.obstacles = obstacles;
+        self
+    }
+
+    pub fn get_obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+
+    /// Top-left pixel of the shape's square bounding box and its side length
+    /// in pixels.
+    fn pixel_box(shape: &Shape, size: usize) -> (isize, isize, usize) {
+        let float_to_coord = |f: f64| (f * (size as f64)).floor() as isize;
 
         let x1 = float_to_coord(shape.position.0);
         let y1 = float_to_coord(shape.position.1);
 
         let size_int = float_to_coord(shape.size.0);
 
-        let x2 = float_to_coord(shape.position.0) + size_int;
-        let y2 = float_to_coord(shape.position.1) + size_int;
+        (x1, y1, (size_int + 1) as usize)
+    }
 
-        let slice_fn = |f: AxisDescription| {
-            let axis = f.axis.0;
-            match axis {
-                0 => Slice::new(0, None, 1),
-                1 => Slice::new(y1, Some(y2 + 1), 1),
-                2 => Slice::new(x1, Some(x2 + 1), 1),
-                _ => panic!("Expected 3-d array but received more dimensions!"),
-            }
-        };
-        let mut slice = image.slice_each_axis_mut(slice_fn);
-        slice.assign(&shape.color.to_ndarray());
+    fn square_coverage(extent: usize) -> Array2<f64> {
+        Array2::ones((extent, extent))
     }
 
-    fn render_circle(image: &mut Array3<f64>, shape: &Shape, size: usize) {
-        let coord_to_float = |f: usize| (f as f64) / (size as f64);
+    fn circle_coverage(shape: &Shape, size: usize) -> Array2<f64> {
+        let coord_to_float = |f: isize| (f as f64) / (size as f64);
 
         // TODO: this might cause issues when we move shapes later
-        let fix_center = |f: f64| ((f * (size as f64)) as usize) as f64 / (size as f64);
+        let fix_center = |f: f64| (f * (size as f64)).floor() / (size as f64);
 
         let radius = shape.size.0 / 2.;
         let x_center = fix_center(shape.position.0 + radius);
         let y_center = fix_center(shape.position.1 + radius);
 
-        // TODO: implement the below numpy code
-        // np.sqrt(((np.mgrid[0:size, 0:size] / size - np.array([[[x_center]], [[y_center]]]))**2).sum(axis=0)) < radius
+        let (x1, y1, extent) = Entry::pixel_box(shape, size);
+
+        // flooring the center can push the circle one pixel past the box
+        let mut coverage = Array2::zeros((extent + 1, extent + 1));
+        Zip::indexed(&mut coverage).par_for_each(|(y, x), v| {
+            let xd = coord_to_float(x1 + x as isize) - x_center;
+            let yd = coord_to_float(y1 + y as isize) - y_center;
+
+            let dist = (xd * xd + yd * yd).sqrt();
+            if dist <= radius {
+                *v = 1.;
+            }
+        });
+        coverage
+    }
+
+    fn glyph_coverage(glyph: char, extent: usize) -> Array2<f64> {
+        let bitmap = font::glyph_bitmap(glyph).expect("Glyph missing from embedded font!");
+        let extent_f = extent as f64;
 
-        // let coords: Array1<f64> = Array1::linspace(0.0, 1.0, size);
-        // let coords = coords.broadcast((size, size)).expect("Failed broadcasting coordinates to 2d grid.");
-        // let coords = ndarray::stack(Axis(0), &[coords, coords.t()]).expect("Failed to stack transposed coordinates!")
+        // local coordinates are relative to the glyph's bounding box
+        Array2::from_shape_fn((extent, extent), |(y, x)| {
+            if font::glyph_covers(bitmap, x as f64 / extent_f, y as f64 / extent_f) {
+                1.
+            } else {
+                0.
+            }
+        })
+    }
 
-        // let offset_coords = coords - array![y_center, x_center].broadcast((2, size, size)).unwrap();
-        // let distances: Array2<f64> = (offset_coords * offset_coords).sum_axis(Axis(0)).into_par_iter().map(|v| v.sqrt()).collect();
-        // let distances = distances.broadcast(image.shape());
-        // TODO: how to set image based on these distances?
+    fn sprite_coverage(sprite: &Sprite, extent: usize) -> Array2<f64> {
+        let extent_f = extent as f64;
 
-        // TODO: improve this? iterating over every pixel and channel
-        Zip::indexed(image.view_mut()).par_map_collect(|(c, y, x), v| {
-            let xf = coord_to_float(x);
-            let yf = coord_to_float(y);
+        Array2::from_shape_fn((extent, extent), |(y, x)| {
+            sprite
+                .sample(x as f64 / extent_f, y as f64 / extent_f)
+                .map_or(0., |rgba| rgba[3])
+        })
+    }
 
-            let xd = xf - x_center;
-            let yd = yf - y_center;
+    fn sprite(&self, index: usize) -> &Sprite {
+        self.sprites.get(index).expect("Sprite index out of range!")
+    }
 
-            let dist = (xd * xd + yd * yd).sqrt();
+    /// Alpha of the shape over its bounding box, indexed by (y, x) relative to
+    /// the top-left pixel of the box.
+    fn coverage(&self, shape: &Shape, size: usize) -> Array2<f64> {
+        let (_, _, extent) = Entry::pixel_box(shape, size);
 
-            if dist <= radius {
-                *v = {
-                    // TODO: this in particular sucks
-                    let color = match c {
-                        0 => shape.color.0,
-                        1 => shape.color.1,
-                        2 => shape.color.2,
-                        _ => panic!("Index out of range"),
-                    };
-                    (color as f64) / 255.
+        match shape.shape_type {
+            ShapeType::Square => Entry::square_coverage(extent),
+            ShapeType::Circle => Entry::circle_coverage(shape, size),
+            ShapeType::Glyph(c) => Entry::glyph_coverage(c, extent),
+            ShapeType::Sprite(i) => Entry::sprite_coverage(self.sprite(i), extent),
+        }
+    }
+
+    /// Fill color of the shape at pixel (x, y) of its bounding box.
+    fn fill_color(&self, shape: &Shape, x: usize, y: usize, extent: usize) -> [f64; 3] {
+        let extent = extent as f64;
+        let (u, v) = (x as f64 / extent, y as f64 / extent);
+
+        match shape.shape_type {
+            ShapeType::Sprite(i) => {
+                let sprite = self.sprite(i);
+                let Some(rgba) = sprite.sample(u, v) else {
+                    return [0.; 3];
+                };
+
+                let mut rgb = [rgba[0], rgba[1], rgba[2]];
+                if sprite.is_tinted() {
+                    for (c, v) in rgb.iter_mut().enumerate() {
+                        *v *= shape.color.channel(c);
+                    }
                 }
-            };
-        });
+                rgb
+            }
+            _ => shape.color_at(u, v),
+        }
+    }
+
+    /// The part of `coverage` within `width` pixels of its edge.
+    fn stroke(coverage: &Array2<f64>, width: usize) -> Array2<f64> {
+        let (height, breadth) = coverage.dim();
+        let radius = width as isize;
+
+        let covered = |y: isize, x: isize| {
+            y >= 0
+                && x >= 0
+                && y < height as isize
+                && x < breadth as isize
+                && coverage[[y as usize, x as usize]] > 0.
+        };
+
+        Array2::from_shape_fn(coverage.dim(), |(y, x)| {
+            let alpha = coverage[[y, x]];
+            if alpha <= 0. {
+                return 0.;
+            }
+
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    if dy * dy + dx * dx <= radius * radius
+                        && !covered(y as isize + dy, x as isize + dx)
+                    {
+                        return alpha;
+                    }
+                }
+            }
+            0.
+        })
+    }
+
+    fn stroke_px(width: f64, size: usize) -> usize {
+        ((width * size as f64).round() as usize).max(1)
+    }
+
+    /// Alpha of everything drawn for the shape over its bounding box, whether
+    /// that is its fill, its outline or both.
+    fn footprint(&self, shape: &Shape, size: usize) -> Array2<f64> {
+        let coverage = self.coverage(shape, size);
+
+        match shape.fill_style {
+            FillStyle::Outline(width) => Entry::stroke(&coverage, Entry::stroke_px(width, size)),
+            // the outline is drawn over the fill
+            FillStyle::Filled | FillStyle::FilledOutline(..) => coverage,
+        }
+    }
+
+    /// Alpha-composites `alpha` onto the image with its top-left corner at
+    /// pixel (x1, y1), skipping anything that falls outside the image.
+    fn paint(
+        image: &mut Array3<f64>,
+        x1: isize,
+        y1: isize,
+        alpha: &Array2<f64>,
+        color: impl Fn(usize, usize) -> [f64; 3],
+    ) {
+        let (_, height, width) = image.dim();
+
+        for ((y, x), &a) in alpha.indexed_iter() {
+            let iy = y1 + y as isize;
+            let ix = x1 + x as isize;
+            if a <= 0. || iy < 0 || ix < 0 || iy >= height as isize || ix >= width as isize {
+                continue;
+            }
+
+            for (c, value) in color(x, y).into_iter().enumerate() {
+                let pixel = &mut image[[c, iy as usize, ix as usize]];
+                *pixel = a * value + (1. - a) * *pixel;
+            }
+        }
+    }
+
+    /// Origins to draw a shape's bounding box at. With wrap-around boundaries,
+    /// shapes crossing an edge are also drawn shifted by the image size.
+    fn copy_origins(
+        &self,
+        x1: isize,
+        y1: isize,
+        (height, width): (usize, usize),
+        size: usize,
+    ) -> Vec<(isize, isize)> {
+        if self.boundary != Boundary::Wrap {
+            return vec![(x1, y1)];
+        }
+
+        let size = size as isize;
+        let shifts = |start: isize, extent: usize| {
+            [-size, 0, size]
+                .into_iter()
+                .filter(move |shift| start + shift < size && start + shift + extent as isize > 0)
+        };
+
+        shifts(y1, height)
+            .flat_map(|dy| shifts(x1, width).map(move |dx| (x1 + dx, y1 + dy)))
+            .collect()
+    }
+
+    fn render_shape(&self, image: &mut Array3<f64>, shape: &Shape, size: usize) {
+        let (x1, y1, extent) = Entry::pixel_box(shape, size);
+        let coverage = self.coverage(shape, size);
+
+        let fill = |x, y| self.fill_color(shape, x, y, extent);
+
+        let stroke = match shape.fill_style {
+            FillStyle::Filled => None,
+            FillStyle::Outline(width) | FillStyle::FilledOutline(width, _) => {
+                Some(Entry::stroke(&coverage, Entry::stroke_px(width, size)))
+            }
+        };
+
+        for (x, y) in self.copy_origins(x1, y1, coverage.dim(), size) {
+            if !matches!(shape.fill_style, FillStyle::Outline(_)) {
+                Entry::paint(image, x, y, &coverage, fill);
+            }
+            if let Some(stroke) = &stroke {
+                match shape.fill_style {
+                    FillStyle::FilledOutline(_, stroke_color) => {
+                        Entry::paint(image, x, y, stroke, |_, _| stroke_color.to_rgb())
+                    }
+                    _ => Entry::paint(image, x, y, stroke, fill),
+                }
+            }
+        }
+    }
+
+    fn render_obstacle(image: &mut Array3<f64>, obstacle: &Obstacle, size: usize) {
+        let float_to_coord = |f: f64| (f * (size as f64)).floor() as isize;
+        let coord_to_float = |f: isize| (f as f64) / (size as f64);
+
+        let (x1, y1, x2, y2) = obstacle.bounds();
+        let (x1, y1) = (float_to_coord(x1), float_to_coord(y1));
+        let (x2, y2) = (float_to_coord(x2), float_to_coord(y2));
+
+        let coverage = Array2::from_shape_fn(
+            ((y2 - y1 + 1) as usize, (x2 - x1 + 1) as usize),
+            |(y, x)| {
+                let xf = coord_to_float(x1 + x as isize);
+                let yf = coord_to_float(y1 + y as isize);
+                if obstacle.covers(xf, yf) {
+                    1.
+                } else {
+                    0.
+                }
+            },
+        );
+        Entry::paint(image, x1, y1, &coverage, |_, _| obstacle.color.to_rgb());
     }
 
     pub fn render_entry(&self, size: u16) -> Array3<f64> {
         let size = size as usize;
         let mut image = Array3::zeros((3, size, size));
 
+        for obstacle in self.obstacles.iter() {
+            Entry::render_obstacle(&mut image, obstacle, size);
+        }
+
         for shape in self.shapes.iter() {
-            match shape.shape_type {
-                ShapeType::Circle => Entry::render_circle(&mut image, shape, size),
-                ShapeType::Square => Entry::render_square(&mut image, shape, size),
-            };
+            self.render_shape(&mut image, shape, size);
         }
         image
     }
 
+    /// Renders `num_frames` frames as a (frames, 3, size, size) array,
+    /// stepping the entry after each one.
+    pub fn render_video(&mut self, num_frames: usize, size: u16, step_size: f64) -> Array4<f64> {
+        let mut video_array = Array4::zeros((num_frames, 3, size as usize, size as usize));
+        for i in 0..num_frames {
+            let array = self.render_entry(size);
+            self.step_entry(step_size);
+
+            video_array
+                .slice_mut(s![i..i + 1, .., .., ..])
+                .assign(&array);
+        }
+
+        video_array
+    }
+
+    /// Index of the topmost shape at each pixel, or -1 where no shape is
+    /// visible. Pixels belong to a shape when it is at least half opaque there.
+    pub fn render_instance_ids(&self, size: u16) -> Array2<i64> {
+        let size = size as usize;
+        let mut ids = Array2::from_elem((size, size), -1);
+
+        for (i, shape) in self.shapes.iter().enumerate() {
+            let (x1, y1, _) = Entry::pixel_box(shape, size);
+            let footprint = self.footprint(shape, size);
+
+            for (x, y) in self.copy_origins(x1, y1, footprint.dim(), size) {
+                for ((fy, fx), &alpha) in footprint.indexed_iter() {
+                    let iy = y + fy as isize;
+                    let ix = x + fx as isize;
+                    if alpha >= 0.5
+                        && iy >= 0
+                        && ix >= 0
+                        && iy < size as isize
+                        && ix < size as isize
+                    {
+                        ids[[iy as usize, ix as usize]] = i as i64;
+                    }
+                }
+            }
+        }
+        ids
+    }
+
+    /// Pixels of a `size` x `size` image inside each shape's silhouette, as
+    /// (x, y), including pixels hidden by other shapes or out of frame. As in
+    /// `render_instance_ids`, pixels belong to a shape when it is at least half
+    /// opaque there.
+    pub(crate) fn shape_pixels(&self, size: u16) -> Vec<Vec<(isize, isize)>> {
+        let size = size as usize;
+
+        self.shapes
+            .iter()
+            .map(|shape| {
+                let (x1, y1, _) = Entry::pixel_box(shape, size);
+                self.coverage(shape, size)
+                    .indexed_iter()
+                    .filter(|(_, &alpha)| alpha >= 0.5)
+                    .map(|((y, x), _)| (x1 + x as isize, y1 + y as isize))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Tracking annotations for every shape in the current state of the
+    /// entry, labelled as frame `frame`.
+    pub fn track_annotations(&self, frame: usize, size: u16) -> Vec<TrackAnnotation> {
+        let ids = self.render_instance_ids(size);
+        let size = size as usize;
+
+        self.shapes
+            .iter()
+            .enumerate()
+            .map(|(i, shape)| {
+                let (x1, y1, _) = Entry::pixel_box(shape, size);
+                let footprint = self.footprint(shape, size);
+
+                let mut total = 0;
+                let (mut min_x, mut min_y) = (usize::MAX, usize::MAX);
+                let (mut max_x, mut max_y) = (0, 0);
+                for ((y, x), &alpha) in footprint.indexed_iter() {
+                    if alpha >= 0.5 {
+                        total += 1;
+                        min_x = min_x.min(x);
+                        min_y = min_y.min(y);
+                        max_x = max_x.max(x);
+                        max_y = max_y.max(y);
+                    }
+                }
+
+                let (bbox, visibility) = if total == 0 {
+                    ((x1 as f64, y1 as f64, 0., 0.), 0.)
+                } else {
+                    let visible = ids.iter().filter(|&&id| id == i as i64).count();
+                    (
+                        (
+                            (x1 + min_x as isize) as f64,
+                            (y1 + min_y as isize) as f64,
+                            (max_x - min_x + 1) as f64,
+                            (max_y - min_y + 1) as f64,
+                        ),
+                        (visible as f64 / total as f64).min(1.),
+                    )
+                };
+
+                TrackAnnotation {
+                    frame,
+                    id: i,
+                    position: (shape.position.0, shape.position.1),
+                    velocity: (shape.velocity.0, shape.velocity.1),
+                    bbox,
+                    visibility,
+                }
+            })
+            .collect()
+    }
+
+    pub fn positions(&self) -> Vec<Position> {
+        self.shapes.iter().map(|shape| shape.position).collect()
+    }
+
+    /// Optical flow in pixels, as (dx, dy) channels, from a frame with instance
+    /// ids `ids` and shapes at positions `from` to a frame with shapes at
+    /// positions `to`. Shapes only translate, so every pixel moves with the
+    /// topmost shape there, and the background stays still.
+    pub fn render_flow(
+        &self,
+        ids: &Array2<i64>,
+        from: &[Position],
+        to: &[Position],
+        size: u16,
+    ) -> Array3<f64> {
+        let displacements: Vec<(f64, f64)> = from
+            .iter()
+            .zip(to)
+            .map(|(start, end)| {
+                let mut dx = end.0 - start.0;
+                let mut dy = end.1 - start.1;
+                if self.boundary == Boundary::Wrap {
+                    // take the short way around rather than across the image
+                    dx = (dx + 0.5).rem_euclid(1.0) - 0.5;
+                    dy = (dy + 0.5).rem_euclid(1.0) - 0.5;
+                }
+                (dx * size as f64, dy * size as f64)
+            })
+            .collect();
+
+        let (height, width) = ids.dim();
+        let mut flow = Array3::zeros((2, height, width));
+        for ((y, x), &id) in ids.indexed_iter() {
+            if id >= 0 {
+                let (dx, dy) = displacements[id as usize];
+                flow[[0, y, x]] = dx;
+                flow[[1, y, x]] = dy;
+            }
+        }
+        flow
+    }
+
+    /// Renders `num_frames` frames as in `render_video`, with the optical flow
+    /// between consecutive frames as `(frames, forward_flow, backward_flow)`.
+    /// Forward flow `i` is from frame `i` to frame `i + 1`, and backward flow
+    /// `i` from frame `i + 1` back to frame `i`.
+    pub fn render_video_with_flow(
+        &mut self,
+        num_frames: usize,
+        size: u16,
+        step_size: f64,
+    ) -> (Array4<f64>, Array4<f64>, Array4<f64>) {
+        let num_flows = num_frames.saturating_sub(1);
+        let mut video_array = Array4::zeros((num_frames, 3, size as usize, size as usize));
+        let mut forward_array = Array4::zeros((num_flows, 2, size as usize, size as usize));
+        let mut backward_array = Array4::zeros((num_flows, 2, size as usize, size as usize));
+        for i in 0..num_frames {
+            let array = self.render_entry(size);
+            video_array
+                .slice_mut(s![i..i + 1, .., .., ..])
+                .assign(&array);
+
+            if i + 1 < num_frames {
+                let ids = self.render_instance_ids(size);
+                let before = self.positions();
+                self.step_entry(step_size);
+                let after = self.positions();
+
+                let forward = self.render_flow(&ids, &before, &after, size);
+                let backward =
+                    self.render_flow(&self.render_instance_ids(size), &after, &before, size);
+                forward_array
+                    .slice_mut(s![i..i + 1, .., .., ..])
+                    .assign(&forward);
+                backward_array
+                    .slice_mut(s![i..i + 1, .., .., ..])
+                    .assign(&backward);
+            }
+        }
+
+        (video_array, forward_array, backward_array)
+    }
+
+    /// Renders `num_frames` frames as in `render_video`, with tracking
+    /// annotations for every shape in every frame, ordered by frame and then
+    /// by id.
+    pub fn render_video_with_tracks(
+        &mut self,
+        num_frames: usize,
+        size: u16,
+        step_size: f64,
+    ) -> (Array4<f64>, Vec<TrackAnnotation>) {
+        let mut video_array = Array4::zeros((num_frames, 3, size as usize, size as usize));
+        let mut tracks = Vec::with_capacity(num_frames * self.shapes.len());
+        for i in 0..num_frames {
+            let array = self.render_entry(size);
+            tracks.extend(self.track_annotations(i, size));
+            self.step_entry(step_size);
+
+            video_array
+                .slice_mut(s![i..i + 1, .., .., ..])
+                .assign(&array);
+        }
+
+        (video_array, tracks)
+    }
+
     pub fn step_entry(&mut self, step_size: f64) {
         for shape in self.shapes.iter_mut() {
-            shape.step_shape(step_size);
+            shape.step_shape(step_size, &self.physics, self.boundary, &self.obstacles);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders a red square covering pixels 2..6 of an 8x8 image as rows of
+    /// 'r' for red, 'b' for blue and '.' for background.
+    fn raster(fill_style: FillStyle) -> Vec<String> {
+        let shape = Shape::new(&ShapeType::Square)
+            .position(0.25, 0.25)
+            .size(0.375)
+            .color(255, 0, 0)
+            .fill_style(fill_style);
+        let image = Entry::new(vec![shape]).render_entry(8);
+
+        (0..8)
+            .map(|y| {
+                (0..8)
+                    .map(|x| match (image[[0, y, x]], image[[2, y, x]]) {
+                        (r, _) if r > 0. => 'r',
+                        (_, b) if b > 0. => 'b',
+                        _ => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn moving_square(x: f64, vx: f64, boundary: Boundary) -> Entry {
+        let shape = Shape::new(&ShapeType::Square)
+            .position(x, 0.25)
+            .size(0.25)
+            .velocity(vx, 0.)
+            .color(255, 0, 0);
+        Entry::new(vec![shape]).boundary(boundary)
+    }
+
+    /// Columns of row 3 of an 8x8 render that are drawn on.
+    fn drawn_columns(entry: &Entry) -> Vec<usize> {
+        let image = entry.render_entry(8);
+        (0..8).filter(|&x| image[[0, 3, x]] > 0.).collect()
+    }
+
+    #[test]
+    fn wrapped_shapes_reenter_on_the_opposite_edge() {
+        let mut entry = moving_square(0.625, 0.25, Boundary::Wrap);
+        entry.step_entry(1.);
+        assert_eq!(entry.shapes()[0].position.0, 0.875);
+        // split across the right and left edges
+        assert_eq!(drawn_columns(&entry), vec![0, 1, 7]);
+
+        entry.step_entry(1.);
+        assert!((entry.shapes()[0].position.0 - 0.125).abs() < 1e-12);
+        assert_eq!(drawn_columns(&entry), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn open_boundaries_let_shapes_leave() {
+        let mut entry = moving_square(0.625, 0.5, Boundary::Open);
+        entry.step_entry(1.);
+        assert_eq!(entry.shapes()[0].position.0, 1.125);
+        assert_eq!(drawn_columns(&entry), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn absorbing_walls_stop_shapes() {
+        let mut entry = moving_square(0.625, 0.5, Boundary::Absorb);
+        entry.step_entry(1.);
+        let shape = &entry.shapes()[0];
+        assert_eq!(shape.position.0, 0.75);
+        assert_eq!(shape.velocity.0, 0.);
+        assert_eq!(drawn_columns(&entry), vec![6, 7]);
+    }
+
+    #[test]
+    fn flow_follows_shape_velocity() {
+        let mut entry = moving_square(0.25, 0.125, Boundary::Reflect);
+        let ids = entry.render_instance_ids(8);
+        let before = entry.positions();
+        entry.step_entry(1.);
+        let after = entry.positions();
+
+        // the box covers pixels 2..5, plus one for rounding
+        let covered = |(y, x): (usize, usize)| (2..5).contains(&y) && (2..5).contains(&x);
+        for (index, &id) in ids.indexed_iter() {
+            assert_eq!(id, if covered(index) { 0 } else { -1 }, "{index:?}");
+        }
+
+        let forward = entry.render_flow(&ids, &before, &after, 8);
+        let backward = entry.render_flow(&entry.render_instance_ids(8), &after, &before, 8);
+        for y in 0..8 {
+            for x in 0..8 {
+                let moved = |dx: usize| covered((y, x - dx.min(x)));
+                assert_eq!(forward[[0, y, x]], if covered((y, x)) { 1. } else { 0. });
+                assert_eq!(
+                    backward[[0, y, x]],
+                    if moved(1) && x > 0 { -1. } else { 0. }
+                );
+                assert_eq!(forward[[1, y, x]], 0.);
+                assert_eq!(backward[[1, y, x]], 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn fill_styles_draw_expected_pixels() {
+        let blank = "........";
+        assert_eq!(
+            raster(FillStyle::Filled),
+            [blank, blank, "..rrrr..", "..rrrr..", "..rrrr..", "..rrrr..", blank, blank]
+        );
+        assert_eq!(
+            raster(FillStyle::Outline(0.125)),
+            [blank, blank, "..rrrr..", "..r..r..", "..r..r..", "..rrrr..", blank, blank]
+        );
+        assert_eq!(
+            raster(FillStyle::FilledOutline(0.125, Color(0, 0, 255))),
+            [blank, blank, "..bbbb..", "..brrb..", "..brrb..", "..bbbb..", blank, blank]
+        );
+    }
+}
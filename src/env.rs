@@ -0,0 +1,409 @@
+use ndarray::Array3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::entry::Entry;
+use super::obstacle::{closest_point_on_segment, Obstacle, ObstacleShape};
+use super::physics::{Boundary, Physics};
+use super::placement::shape_center;
+use super::shape::{Shape, ShapeType};
+
+/// Number of actions accepted by `Action::Discrete`.
+pub const NUM_DISCRETE_ACTIONS: usize = 5;
+
+/// Tries at placing a shape away from the others before giving up and
+/// accepting an overlap.
+const MAX_PLACEMENT_TRIES: usize = 100;
+
+/// Built-in tasks for `Environment`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Task {
+    /// Touch a static target. Every step costs 0.01 and reaching the target
+    /// gives 1 and ends the episode. Static obstacles are in the way.
+    ReachTarget,
+    /// Dodge shapes bouncing around the scene. Every step survived gives 0.01
+    /// and touching a shape costs 1 and ends the episode. There are no static
+    /// obstacles.
+    AvoidShapes,
+    /// Move without hitting static obstacles. Every step survived gives 0.01
+    /// and touching an obstacle at any point of a step costs 1 and ends the
+    /// episode.
+    AvoidObstacles,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+    /// 0: do nothing, 1: up, 2: down, 3: left, 4: right.
+    Discrete(usize),
+    /// Acceleration along x and y, each clamped to [-1, 1].
+    Continuous(f64, f64),
+}
+
+#[derive(Debug)]
+pub struct Step {
+    pub observation: Array3<f64>,
+    pub reward: f64,
+    pub done: bool,
+}
+
+/// A reinforcement learning environment where the first shape of an `Entry`
+/// is an agent accelerated by actions. Observations are rendered frames.
+#[derive(Debug)]
+pub struct Environment {
+    task: Task,
+    image_size: u16,
+    step_size: f64,
+    acceleration: f64,
+    max_speed: f64,
+    agent_size: f64,
+    num_obstacles: usize,
+    max_steps: usize,
+    rng: StdRng,
+    entry: Entry,
+    num_steps: usize,
+}
+
+impl Environment {
+    pub fn new(task: Task) -> Self {
+        Environment {
+            task,
+            image_size: 64,
+            step_size: 0.05,
+            acceleration: 2.0,
+            max_speed: 0.5,
+            agent_size: 0.1,
+            num_obstacles: 3,
+            max_steps: 200,
+            rng: StdRng::from_entropy(),
+            entry: Entry::new(Vec::new()),
+            num_steps: 0,
+        }
+    }
+
+    pub fn image_size(mut self, image_size: u16) -> Self {
+        self.image_size = image_size;
+        self
+    }
+
+    pub fn step_size(mut self, step_size: f64) -> Self {
+        self.step_size = step_size;
+        self
+    }
+
+    /// Acceleration of the agent under a full action.
+    pub fn acceleration(mut self, acceleration: f64) -> Self {
+        self.acceleration = acceleration;
+        self
+    }
+
+    pub fn max_speed(mut self, max_speed: f64) -> Self {
+        self.max_speed = max_speed;
+        self
+    }
+
+    pub fn agent_size(mut self, agent_size: f64) -> Self {
+        self.agent_size = agent_size;
+        self
+    }
+
+    /// Static obstacles for `Task::ReachTarget` and `Task::AvoidObstacles`, or
+    /// shapes to dodge for `Task::AvoidShapes`.
+    pub fn num_obstacles(mut self, num_obstacles: usize) -> Self {
+        self.num_obstacles = num_obstacles;
+        self
+    }
+
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn entry(&self) -> &Entry {
+        &self.entry
+    }
+
+    /// Top-left position for a box of `size` that doesn't overlap any of
+    /// `others`, given as (x, y, size).
+    fn random_position(&mut self, size: f64, others: &[(f64, f64, f64)]) -> (f64, f64) {
+        let mut position = (0.0, 0.0);
+        for _ in 0..MAX_PLACEMENT_TRIES {
+            position = (
+                self.rng.gen_range(0.0..=1.0 - size),
+                self.rng.gen_range(0.0..=1.0 - size),
+            );
+
+            let (x, y) = position;
+            let overlaps = others.iter().any(|&(ox, oy, other_size)| {
+                x < ox + other_size && ox < x + size && y < oy + other_size && oy < y + size
+            });
+            if !overlaps {
+                break;
+            }
+        }
+        position
+    }
+
+    /// Adds `num_obstacles` square obstacles away from the `placed` boxes.
+    fn add_obstacles(&mut self, obstacles: &mut Vec<Obstacle>, placed: &mut Vec<(f64, f64, f64)>) {
+        for _ in 0..self.num_obstacles {
+            let size = self.rng.gen_range(0.05..=0.25);
+            let (x, y) = self.random_position(size, placed);
+            obstacles.push(Obstacle::rectangle(x, y, size, size));
+            placed.push((x, y, size));
+        }
+    }
+
+    /// Starts a new episode, reseeding the environment first if `seed` is
+    /// given, and returns the first observation.
+    pub fn reset(&mut self, seed: Option<u64>) -> Array3<f64> {
+        if let Some(seed) = seed {
+            self.rng = StdRng::seed_from_u64(seed);
+        }
+        self.num_steps = 0;
+
+        let (x, y) = self.random_position(self.agent_size, &[]);
+        let agent = Shape::new(&ShapeType::Circle)
+            .size(self.agent_size)
+            .position(x, y)
+            .color(0xff, 0xff, 0xff);
+        let mut placed = vec![(x, y, self.agent_size)];
+        let mut shapes = vec![agent];
+        let mut obstacles = Vec::with_capacity(self.num_obstacles);
+
+        match self.task {
+            Task::ReachTarget => {
+                let (x, y) = self.random_position(self.agent_size, &placed);
+                shapes.push(
+                    Shape::new(&ShapeType::Square)
+                        .size(self.agent_size)
+                        .position(x, y)
+                        .color(0x00, 0xff, 0x00),
+                );
+                placed.push((x, y, self.agent_size));
+                self.add_obstacles(&mut obstacles, &mut placed);
+            }
+            Task::AvoidObstacles => self.add_obstacles(&mut obstacles, &mut placed),
+            Task::AvoidShapes => {
+                for _ in 0..self.num_obstacles {
+                    let (x, y) = self.random_position(self.agent_size, &placed);
+                    let velocity = (
+                        self.rng.gen_range(-self.max_speed..=self.max_speed),
+                        self.rng.gen_range(-self.max_speed..=self.max_speed),
+                    );
+                    shapes.push(
+                        Shape::new(&ShapeType::Square)
+                            .size(self.agent_size)
+                            .position(x, y)
+                            .velocity(velocity.0, velocity.1)
+                            .color(0xff, 0x00, 0x00),
+                    );
+                    placed.push((x, y, self.agent_size));
+                }
+            }
+        }
+
+        self.entry = Entry::new(shapes).obstacles(obstacles);
+        self.entry.render_entry(self.image_size)
+    }
+
+    fn touches_agent(&self, shape: &Shape) -> bool {
+        let agent = &self.entry.shapes()[0];
+        let (ax, ay) = shape_center(agent);
+        let (sx, sy) = shape_center(shape);
+        let distance = ((ax - sx).powi(2) + (ay - sy).powi(2)).sqrt();
+        distance < (agent.size.0 + shape.size.0) / 2.
+    }
+
+    /// Whether the agent, as a circle, touches `obstacle`.
+    fn touches_obstacle(agent: &Shape, obstacle: &Obstacle) -> bool {
+        let radius = agent.size.0 / 2.;
+        let center = shape_center(agent);
+        let (closest, reach) = match obstacle.shape {
+            ObstacleShape::Rectangle { .. } => {
+                let (x1, y1, x2, y2) = obstacle.bounds();
+                ((center.0.clamp(x1, x2), center.1.clamp(y1, y2)), radius)
+            }
+            ObstacleShape::Segment {
+                start,
+                end,
+                thickness,
+            } => (
+                closest_point_on_segment(start, end, center),
+                radius + thickness / 2.,
+            ),
+        };
+        (center.0 - closest.0).hypot(center.1 - closest.1) < reach
+    }
+
+    /// Whether the agent touches an obstacle at any point of its next step.
+    /// Shapes bounce off obstacles within a step, so this follows a copy of
+    /// the agent through them in substeps of at most a quarter of its size.
+    fn agent_will_hit_obstacle(&self) -> bool {
+        let agent = &self.entry.shapes()[0];
+        let mut ghost = Shape::new(&ShapeType::Circle)
+            .size(agent.size.0)
+            .position(agent.position.0, agent.position.1)
+            .velocity(agent.velocity.0, agent.velocity.1);
+
+        let speed = agent.velocity.0.hypot(agent.velocity.1);
+        let substeps = ((speed * self.step_size / (agent.size.0 / 4.)).ceil() as usize).max(1);
+        let substep_size = self.step_size / substeps as f64;
+        (0..substeps).any(|_| {
+            ghost.step_shape(substep_size, &Physics::new(), Boundary::Reflect, &[]);
+            self.entry
+                .get_obstacles()
+                .iter()
+                .any(|obstacle| Environment::touches_obstacle(&ghost, obstacle))
+        })
+    }
+
+    /// Accelerates the agent by `action`, advances the scene by one step and
+    /// returns the new observation, the reward and whether the episode ended.
+    pub fn step(&mut self, action: Action) -> Step {
+        let (ax, ay) = match action {
+            Action::Discrete(0) => (0.0, 0.0),
+            Action::Discrete(1) => (0.0, -1.0),
+            Action::Discrete(2) => (0.0, 1.0),
+            Action::Discrete(3) => (-1.0, 0.0),
+            Action::Discrete(4) => (1.0, 0.0),
+            Action::Discrete(a) => panic!("Invalid discrete action {a}"),
+            Action::Continuous(x, y) => (x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0)),
+        };
+
+        let (acceleration, step_size, max_speed) =
+            (self.acceleration, self.step_size, self.max_speed);
+        let agent = self
+            .entry
+            .shapes_mut()
+            .first_mut()
+            .expect("Environment must be reset before stepping!");
+        agent.velocity.0 += ax * acceleration * step_size;
+        agent.velocity.1 += ay * acceleration * step_size;
+
+        let speed = (agent.velocity.0.powi(2) + agent.velocity.1.powi(2)).sqrt();
+        if speed > max_speed {
+            agent.velocity = agent.velocity * (max_speed / speed);
+        }
+
+        let hit_obstacle = self.task == Task::AvoidObstacles && self.agent_will_hit_obstacle();
+        self.entry.step_entry(step_size);
+        self.num_steps += 1;
+
+        let touched = hit_obstacle
+            || self.entry.shapes()[1..]
+                .iter()
+                .any(|shape| self.touches_agent(shape));
+        let (reward, finished) = match (self.task, touched) {
+            (Task::ReachTarget, true) => (1.0, true),
+            (Task::ReachTarget, false) => (-0.01, false),
+            (Task::AvoidShapes | Task::AvoidObstacles, true) => (-1.0, true),
+            (Task::AvoidShapes | Task::AvoidObstacles, false) => (0.01, false),
+        };
+
+        Step {
+            observation: self.entry.render_entry(self.image_size),
+            reward,
+            done: finished || self.num_steps >= self.max_steps,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::{Position, Velocity};
+
+    /// An environment without static obstacles, with the agent in the middle
+    /// and the target or the shape to dodge at `(x, y)`, standing still.
+    fn environment(task: Task, x: f64, y: f64) -> Environment {
+        let num_obstacles = match task {
+            Task::ReachTarget | Task::AvoidObstacles => 0,
+            Task::AvoidShapes => 1,
+        };
+        let mut environment = Environment::new(task)
+            .num_obstacles(num_obstacles)
+            .image_size(16);
+        environment.reset(Some(0));
+
+        let shapes = environment.entry.shapes_mut();
+        shapes[0].position = Position(0.45, 0.45);
+        shapes[1].position = Position(x, y);
+        shapes[1].velocity = Velocity(0.0, 0.0);
+        environment
+    }
+
+    #[test]
+    fn seeds_reproduce_episodes() {
+        let mut a = Environment::new(Task::AvoidShapes);
+        let mut b = Environment::new(Task::AvoidShapes);
+        assert_eq!(a.reset(Some(7)), b.reset(Some(7)));
+        for action in [1, 4, 4, 2, 0] {
+            let (a, b) = (
+                a.step(Action::Discrete(action)),
+                b.step(Action::Discrete(action)),
+            );
+            assert_eq!(a.observation, b.observation);
+            assert_eq!((a.reward, a.done), (b.reward, b.done));
+        }
+    }
+
+    #[test]
+    fn discrete_actions_move_the_agent() {
+        let directions: [(f64, f64); 5] =
+            [(0.0, 0.0), (0.0, -1.0), (0.0, 1.0), (-1.0, 0.0), (1.0, 0.0)];
+        for (action, (dx, dy)) in directions.into_iter().enumerate() {
+            let mut environment = environment(Task::ReachTarget, 0.0, 0.0);
+            environment.step(Action::Discrete(action));
+
+            let agent = &environment.entry().shapes()[0];
+            assert_eq!(agent.position.0.total_cmp(&0.45), dx.total_cmp(&0.0));
+            assert_eq!(agent.position.1.total_cmp(&0.45), dy.total_cmp(&0.0));
+        }
+    }
+
+    #[test]
+    fn reaching_the_target_ends_the_episode() {
+        let step = environment(Task::ReachTarget, 0.0, 0.0).step(Action::Discrete(0));
+        assert_eq!((step.reward, step.done), (-0.01, false));
+
+        let step = environment(Task::ReachTarget, 0.5, 0.45).step(Action::Discrete(0));
+        assert_eq!((step.reward, step.done), (1.0, true));
+    }
+
+    #[test]
+    fn touching_a_shape_ends_the_episode() {
+        let step = environment(Task::AvoidShapes, 0.0, 0.0).step(Action::Discrete(0));
+        assert_eq!((step.reward, step.done), (0.01, false));
+
+        let step = environment(Task::AvoidShapes, 0.5, 0.45).step(Action::Discrete(0));
+        assert_eq!((step.reward, step.done), (-1.0, true));
+    }
+
+    #[test]
+    fn episodes_end_after_max_steps() {
+        let mut environment = environment(Task::AvoidShapes, 0.0, 0.0).max_steps(2);
+        assert!(!environment.step(Action::Discrete(0)).done);
+        assert!(environment.step(Action::Discrete(0)).done);
+    }
+
+    #[test]
+    fn hitting_an_obstacle_ends_the_episode() {
+        let mut environment = Environment::new(Task::AvoidObstacles).image_size(16);
+        environment.reset(Some(0));
+        assert_eq!(environment.entry().get_obstacles().len(), 3);
+
+        // the agent would bounce off the wall within the step, ending it
+        // clear of the wall
+        for (velocity, reward, done) in [(-0.5, 0.01, false), (0.5, -1.0, true)] {
+            let agent = Shape::new(&ShapeType::Circle)
+                .size(0.1)
+                .position(0.45, 0.45)
+                .velocity(velocity, 0.0);
+            environment.entry =
+                Entry::new(vec![agent]).obstacles(vec![Obstacle::rectangle(0.56, 0.0, 0.1, 1.0)]);
+
+            let step = environment.step(Action::Discrete(0));
+            assert_eq!((step.reward, step.done), (reward, done));
+        }
+    }
+}
@@ -0,0 +1,512 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Cursor};
+use std::path::{Path, PathBuf};
+
+use image::ImageOutputFormat;
+use ndarray::{Array3, ArrayD, IxDyn};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use super::dataset::Dataset;
+use super::entry::Entry;
+use super::ndarray_to_image;
+use super::npy::{npy_bytes, write_npy, NpzWriter};
+use super::tar::TarWriter;
+use super::tracking::TrackAnnotation;
+
+/// Calls `frame` on each of the frames of a random entry, stepping it in
+/// between. Without `video`, the entry is a single frame image. Fails if the
+/// entry could not be generated.
+fn render_sample<T>(
+    dataset: &Dataset,
+    video: Option<(usize, f64)>,
+    mut frame: impl FnMut(&Entry, usize) -> T,
+) -> io::Result<Vec<T>> {
+    let mut entry = dataset
+        .try_generate_random_entry()
+        .map_err(io::Error::other)?;
+    let (num_frames, step_size) = video.unwrap_or((1, 0.0));
+
+    Ok((0..num_frames)
+        .map(|i| {
+            let value = frame(&entry, i);
+            entry.step_entry(step_size);
+            value
+        })
+        .collect())
+}
+
+/// Image values as bytes, as in `ndarray_to_image`.
+fn image_bytes(image: &Array3<f64>) -> impl Iterator<Item = u8> + '_ {
+    image.iter().map(|v| (v * 255.).clamp(0., 255.) as u8)
+}
+
+/// File layouts for `NpyExporter`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArrayFormat {
+    /// A `shard_000000_images.npy` and a `shard_000000_masks.npy` per shard,
+    /// which `numpy.load` can memory-map.
+    Npy,
+    /// A `shard_000000.npz` per shard, holding `images` and `masks`.
+    Npz,
+}
+
+/// Pre-generates samples of a `Dataset` as sharded NumPy arrays. Images are
+/// stored as uint8 arrays of shape (samples, 3, size, size), or (samples,
+/// frames, 3, size, size) for videos, with uint8 instance masks of the same
+/// shape minus the channels. Masks are 0 for the background and `i + 1` for
+/// the shape with id `i` in `Entry::render_instance_ids`, so entries can have
+/// at most 255 shapes.
+#[derive(Debug)]
+pub struct NpyExporter {
+    format: ArrayFormat,
+    size: u16,
+    video: Option<(usize, f64)>,
+    shard_size: usize,
+}
+
+impl NpyExporter {
+    pub fn new(size: u16) -> Self {
+        NpyExporter {
+            format: ArrayFormat::Npy,
+            size,
+            video: None,
+            shard_size: 1000,
+        }
+    }
+
+    pub fn format(mut self, format: ArrayFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Exports videos of `num_frames` frames instead of images.
+    pub fn videos(mut self, num_frames: usize, step_size: f64) -> Self {
+        self.video = Some((num_frames, step_size));
+        self
+    }
+
+    /// Number of samples in each shard. The last shard may hold fewer.
+    pub fn shard_size(mut self, shard_size: usize) -> Self {
+        if shard_size == 0 {
+            panic!("Shard size must be positive!");
+        }
+
+        self.shard_size = shard_size;
+        self
+    }
+
+    pub fn num_shards(&self, num_samples: usize) -> usize {
+        num_samples.div_ceil(self.shard_size)
+    }
+
+    /// Generates and writes shard number `shard` of an export of
+    /// `num_samples` samples to `dir`, returning the paths written.
+    pub fn write_shard(
+        &self,
+        dataset: &Dataset,
+        dir: impl AsRef<Path>,
+        shard: usize,
+        num_samples: usize,
+    ) -> io::Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        let start = shard * self.shard_size;
+        let count = num_samples.saturating_sub(start).min(self.shard_size);
+
+        // convert each sample to bytes as it is rendered, so a shard never
+        // holds more than its uint8 arrays
+        let samples: Vec<(Vec<u8>, Vec<u8>)> = (0..count)
+            .into_par_iter()
+            .map(|_| {
+                let frames: Vec<io::Result<_>> = render_sample(dataset, self.video, |entry, _| {
+                    let image: Vec<u8> = image_bytes(&entry.render_entry(self.size)).collect();
+                    let mask = entry
+                        .render_instance_ids(self.size)
+                        .iter()
+                        .map(|&id| {
+                            u8::try_from(id + 1).map_err(|_| {
+                                io::Error::new(
+                                    io::ErrorKind::InvalidInput,
+                                    "uint8 masks hold at most 255 shapes",
+                                )
+                            })
+                        })
+                        .collect::<io::Result<Vec<u8>>>()?;
+                    Ok((image, mask))
+                })?;
+
+                let (mut images, mut masks) = (Vec::new(), Vec::new());
+                for frame in frames {
+                    let (image, mask) = frame?;
+                    images.extend(image);
+                    masks.extend(mask);
+                }
+                Ok((images, masks))
+            })
+            .collect::<io::Result<_>>()?;
+
+        let size = self.size as usize;
+        let mut mask_shape = vec![count];
+        if let Some((num_frames, _)) = self.video {
+            mask_shape.push(num_frames);
+        }
+        let mut image_shape = mask_shape.clone();
+        image_shape.push(3);
+        image_shape.extend([size, size]);
+        mask_shape.extend([size, size]);
+
+        let (mut images, mut masks) = (Vec::new(), Vec::new());
+        for (sample_images, sample_masks) in samples {
+            images.extend(sample_images);
+            masks.extend(sample_masks);
+        }
+        let images =
+            ArrayD::from_shape_vec(IxDyn(&image_shape), images).expect("Failed to stack images!");
+        let masks =
+            ArrayD::from_shape_vec(IxDyn(&mask_shape), masks).expect("Failed to stack masks!");
+
+        match self.format {
+            ArrayFormat::Npy => {
+                let images_path = dir.join(format!("shard_{shard:06}_images.npy"));
+                let masks_path = dir.join(format!("shard_{shard:06}_masks.npy"));
+                write_npy(BufWriter::new(File::create(&images_path)?), &images)?;
+                write_npy(BufWriter::new(File::create(&masks_path)?), &masks)?;
+                Ok(vec![images_path, masks_path])
+            }
+            ArrayFormat::Npz => {
+                let path = dir.join(format!("shard_{shard:06}.npz"));
+                let mut npz = NpzWriter::new(BufWriter::new(File::create(&path)?));
+                npz.add_array("images", &images)?;
+                npz.add_array("masks", &masks)?;
+                npz.finish()?;
+                Ok(vec![path])
+            }
+        }
+    }
+
+    /// Generates and writes `num_samples` samples to `dir`, creating it if
+    /// needed, and returns the paths written.
+    pub fn export(
+        &self,
+        dataset: &Dataset,
+        dir: impl AsRef<Path>,
+        num_samples: usize,
+    ) -> io::Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let mut paths = Vec::new();
+        for shard in 0..self.num_shards(num_samples) {
+            paths.extend(self.write_shard(dataset, dir, shard, num_samples)?);
+        }
+        Ok(paths)
+    }
+}
+
+/// How `TarExporter` stores the pixels of each sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleEncoding {
+    /// `<key>.png` for images, and `<key>.<frame>.png` per frame for videos.
+    Png,
+    /// `<key>.npy` holding a uint8 (3, size, size) or (frames, 3, size, size)
+    /// array.
+    Npy,
+}
+
+/// Metadata stored as `<key>.json` next to each sample.
+#[derive(Serialize)]
+struct SampleMetadata<'a> {
+    key: &'a str,
+    size: u16,
+    /// Shapes as they were in the first frame.
+    shapes: serde_json::Value,
+    tracks: Vec<TrackAnnotation>,
+}
+
+/// Pre-generates samples of a `Dataset` as WebDataset style tar shards. Each
+/// sample is a group of files sharing a zero-padded key, its pixels and a
+/// `.json` with its shapes and their per-frame tracking annotations.
+#[derive(Debug)]
+pub struct TarExporter {
+    encoding: SampleEncoding,
+    size: u16,
+    video: Option<(usize, f64)>,
+    shard_bytes: u64,
+}
+
+/// Samples rendered in parallel at once before being written in order.
+const TAR_BATCH_SIZE: usize = 64;
+
+impl TarExporter {
+    pub fn new(size: u16) -> Self {
+        TarExporter {
+            encoding: SampleEncoding::Png,
+            size,
+            video: None,
+            shard_bytes: 1 << 30,
+        }
+    }
+
+    pub fn encoding(mut self, encoding: SampleEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Exports videos of `num_frames` frames instead of images.
+    pub fn videos(mut self, num_frames: usize, step_size: f64) -> Self {
+        self.video = Some((num_frames, step_size));
+        self
+    }
+
+    /// Target size of each shard in bytes. A new shard is started before a
+    /// sample that would take the current one past it, so shards only exceed
+    /// it when holding a single sample.
+    pub fn shard_bytes(mut self, shard_bytes: u64) -> Self {
+        self.shard_bytes = shard_bytes;
+        self
+    }
+
+    /// Files of sample `index` as (name, contents).
+    fn render_files(&self, dataset: &Dataset, index: usize) -> io::Result<Vec<(String, Vec<u8>)>> {
+        let key = format!("{index:09}");
+        let mut shapes = serde_json::Value::Null;
+        let frames = render_sample(dataset, self.video, |entry, i| {
+            if i == 0 {
+                shapes = serde_json::to_value(entry.shapes()).expect("Failed to serialize shapes!");
+            }
+            (
+                entry.render_entry(self.size),
+                entry.track_annotations(i, self.size),
+            )
+        })?;
+
+        let mut files = Vec::new();
+        match self.encoding {
+            SampleEncoding::Png => {
+                for (i, (image, _)) in frames.iter().enumerate() {
+                    let mut png = Cursor::new(Vec::new());
+                    ndarray_to_image(image.clone(), self.size as u32)
+                        .write_to(&mut png, ImageOutputFormat::Png)
+                        .expect("Failed to encode image!");
+                    let name = match self.video {
+                        Some(_) => format!("{key}.{i:04}.png"),
+                        None => format!("{key}.png"),
+                    };
+                    files.push((name, png.into_inner()));
+                }
+            }
+            SampleEncoding::Npy => {
+                let size = self.size as usize;
+                let mut shape = vec![3, size, size];
+                if self.video.is_some() {
+                    shape.insert(0, frames.len());
+                }
+                let array = ArrayD::from_shape_vec(
+                    IxDyn(&shape),
+                    frames
+                        .iter()
+                        .flat_map(|(image, _)| image_bytes(image))
+                        .collect(),
+                )
+                .expect("Failed to stack frames!");
+                files.push((format!("{key}.npy"), npy_bytes(&array)));
+            }
+        }
+
+        let metadata = SampleMetadata {
+            key: &key,
+            size: self.size,
+            shapes,
+            tracks: frames.into_iter().flat_map(|(_, tracks)| tracks).collect(),
+        };
+        files.push((
+            format!("{key}.json"),
+            serde_json::to_vec(&metadata).expect("Failed to serialize metadata!"),
+        ));
+        Ok(files)
+    }
+
+    /// Generates `num_samples` samples and writes them to `shard_000000.tar`,
+    /// `shard_000001.tar`, ... in `dir`, creating it if needed. Returns the
+    /// paths written.
+    pub fn export(
+        &self,
+        dataset: &Dataset,
+        dir: impl AsRef<Path>,
+        num_samples: usize,
+    ) -> io::Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let mut paths: Vec<PathBuf> = Vec::new();
+        let mut shard: Option<TarWriter<BufWriter<File>>> = None;
+        for start in (0..num_samples).step_by(TAR_BATCH_SIZE) {
+            let batch: Vec<_> = (start..num_samples.min(start + TAR_BATCH_SIZE))
+                .into_par_iter()
+                .map(|index| self.render_files(dataset, index))
+                .collect::<io::Result<_>>()?;
+
+            for files in batch {
+                let sample_bytes: u64 = files
+                    .iter()
+                    .map(|(_, data)| TarWriter::<File>::entry_size(data.len()))
+                    .sum();
+
+                let full = shard.as_ref().is_some_and(|tar| {
+                    tar.bytes_written() > 0 && tar.bytes_written() + sample_bytes > self.shard_bytes
+                });
+                if full {
+                    shard.take().expect("Shard was checked above").finish()?;
+                }
+
+                let tar = match &mut shard {
+                    Some(tar) => tar,
+                    None => {
+                        let path = dir.join(format!("shard_{:06}.tar", paths.len()));
+                        let file = BufWriter::new(File::create(&path)?);
+                        paths.push(path);
+                        shard.insert(TarWriter::new(file))
+                    }
+                };
+                for (name, data) in files {
+                    tar.add_file(&name, &data)?;
+                }
+            }
+        }
+
+        if let Some(tar) = shard {
+            tar.finish()?;
+        }
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Placement, ShapeType};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("funnyshapes_{name}_{}", std::process::id()))
+    }
+
+    /// Names of the files in a tar archive, in order.
+    fn tar_names(bytes: &[u8]) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut offset = 0;
+        while bytes[offset] != 0 {
+            let header = &bytes[offset..offset + 512];
+            let name = header[..100].split(|&b| b == 0).next().unwrap();
+            names.push(String::from_utf8(name.to_vec()).unwrap());
+
+            let size = std::str::from_utf8(&header[124..135]).unwrap();
+            let size = usize::from_str_radix(size.trim_end_matches('\0'), 8).unwrap();
+            offset += 512 + size.div_ceil(512) * 512;
+        }
+        names
+    }
+
+    #[test]
+    fn generation_failures_are_errors() {
+        let dataset = Dataset::new()
+            .shape_types(vec![ShapeType::Square])
+            .add_color(255, 0, 0)
+            .size(0.5)
+            .num_shapes(5)
+            .placement(Placement::NoOverlap)
+            .max_placement_tries(10);
+
+        let dir = temp_dir("failed_npy");
+        assert!(NpyExporter::new(16).export(&dataset, &dir, 2).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+
+        let dir = temp_dir("failed_tar");
+        assert!(TarExporter::new(16).export(&dataset, &dir, 2).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn npy_shards_hold_uint8_frames() {
+        let dataset = Dataset::new()
+            .shape_types(vec![ShapeType::Square])
+            .add_color(255, 0, 0)
+            .num_shapes(2);
+        let dir = temp_dir("npy_video");
+        let paths = NpyExporter::new(8)
+            .videos(3, 0.1)
+            .shard_size(2)
+            .export(&dataset, &dir, 3)
+            .unwrap();
+
+        let sizes: Vec<usize> = paths
+            .iter()
+            .map(|path| fs::read(path).unwrap().len())
+            .collect();
+        let masks = fs::read(&paths[3]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<_> = paths.iter().map(|path| path.file_name().unwrap()).collect();
+        assert_eq!(
+            names,
+            [
+                "shard_000000_images.npy",
+                "shard_000000_masks.npy",
+                "shard_000001_images.npy",
+                "shard_000001_masks.npy"
+            ]
+        );
+        // headers are padded to 64 bytes, followed by one byte per value
+        assert_eq!(
+            sizes,
+            [
+                128 + 2 * 3 * 3 * 8 * 8,
+                128 + 2 * 3 * 8 * 8,
+                128 + 3 * 3 * 8 * 8,
+                128 + 3 * 8 * 8
+            ]
+        );
+        assert!(masks[128..].iter().all(|&id| id <= 2));
+    }
+
+    #[test]
+    fn tar_shards_hold_whole_samples() {
+        let dataset = Dataset::new()
+            .shape_types(vec![ShapeType::Square])
+            .add_color(255, 0, 0)
+            .num_shapes(2);
+        let shard_bytes = 16 * 1024;
+
+        for (encoding, extension) in [(SampleEncoding::Png, "png"), (SampleEncoding::Npy, "npy")] {
+            let dir = temp_dir(&format!("tar_{extension}"));
+            let paths = TarExporter::new(16)
+                .encoding(encoding)
+                .shard_bytes(shard_bytes)
+                .export(&dataset, &dir, 20)
+                .unwrap();
+            let shards: Vec<Vec<u8>> = paths.iter().map(|path| fs::read(path).unwrap()).collect();
+            fs::remove_dir_all(&dir).unwrap();
+
+            assert!(paths.len() > 1);
+            let mut keys = Vec::new();
+            for (i, (path, shard)) in paths.iter().zip(&shards).enumerate() {
+                assert_eq!(
+                    path.file_name().unwrap(),
+                    format!("shard_{i:06}.tar").as_str()
+                );
+                // the data is followed by the two block end of archive marker
+                assert!(shard.len() as u64 <= shard_bytes + 1024);
+
+                // each sample is its pixels then its metadata
+                let names = tar_names(shard);
+                for pair in names.chunks(2) {
+                    let key = pair[0].strip_suffix(&format!(".{extension}")).unwrap();
+                    assert_eq!(pair[1], format!("{key}.json"));
+                    keys.push(key.to_string());
+                }
+            }
+
+            let expected: Vec<String> = (0..20).map(|i| format!("{i:09}")).collect();
+            assert_eq!(keys, expected);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+// 5x7 bitmap font used to render `ShapeType::Glyph`.
+// Each glyph is 7 rows, with the 5 lowest bits of each row being the pixels
+// (most significant bit is the leftmost column).
+
+pub const GLYPH_WIDTH: usize = 5;
+pub const GLYPH_HEIGHT: usize = 7;
+
+type Bitmap = [u8; GLYPH_HEIGHT];
+
+#[rustfmt::skip]
+const DIGITS: [Bitmap; 10] = [
+    [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110], // 0
+    [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // 1
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111], // 2
+    [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110], // 3
+    [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010], // 4
+    [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110], // 5
+    [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110], // 6
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000], // 7
+    [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110], // 8
+    [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100], // 9
+];
+
+#[rustfmt::skip]
+const LETTERS: [Bitmap; 26] = [
+    [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001], // A
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110], // B
+    [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110], // C
+    [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100], // D
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111], // E
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000], // F
+    [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111], // G
+    [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001], // H
+    [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // I
+    [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100], // J
+    [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001], // K
+    [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111], // L
+    [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001], // M
+    [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001], // N
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // O
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000], // P
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101], // Q
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001], // R
+    [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110], // S
+    [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100], // T
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // U
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // V
+    [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010], // W
+    [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001], // X
+    [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100], // Y
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111], // Z
+];
+
+/// Returns the bitmap for `c`, or `None` if the font has no glyph for it.
+/// Lowercase letters share the uppercase glyphs.
+pub fn glyph_bitmap(c: char) -> Option<&'static Bitmap> {
+    match c {
+        '0'..='9' => Some(&DIGITS[c as usize - '0' as usize]),
+        'A'..='Z' => Some(&LETTERS[c as usize - 'A' as usize]),
+        'a'..='z' => Some(&LETTERS[c as usize - 'a' as usize]),
+        _ => None,
+    }
+}
+
+/// Whether `bitmap` covers the point `(u, v)`, given in [0, 1) relative
+/// to the glyph's square bounding box. The 5x7 bitmap is centered
+/// horizontally in a 7x7 grid so glyphs keep their aspect ratio.
+pub fn glyph_covers(bitmap: &Bitmap, u: f64, v: f64) -> bool {
+    let padding = (GLYPH_HEIGHT - GLYPH_WIDTH) / 2;
+    let column = (u * GLYPH_HEIGHT as f64).floor() as isize - padding as isize;
+    let row = (v * GLYPH_HEIGHT as f64).floor() as isize;
+
+    if column < 0 || column >= GLYPH_WIDTH as isize || row < 0 || row >= GLYPH_HEIGHT as isize {
+        return false;
+    }
+
+    (bitmap[row as usize] >> (GLYPH_WIDTH - 1 - column as usize)) & 1 == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders `c` at one sample per font pixel, in the 7x7 grid.
+    fn raster(c: char) -> Vec<String> {
+        let bitmap = glyph_bitmap(c).unwrap();
+        (0..GLYPH_HEIGHT)
+            .map(|row| {
+                (0..GLYPH_HEIGHT)
+                    .map(|column| {
+                        let u = (column as f64 + 0.5) / GLYPH_HEIGHT as f64;
+                        let v = (row as f64 + 0.5) / GLYPH_HEIGHT as f64;
+                        if glyph_covers(bitmap, u, v) {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn glyphs_cover_their_bitmap() {
+        assert_eq!(
+            raster('T'),
+            [".#####.", "...#...", "...#...", "...#...", "...#...", "...#...", "...#..."]
+        );
+        assert_eq!(raster('t'), raster('T'));
+        assert!(!glyph_covers(glyph_bitmap('T').unwrap(), 1.0, 0.0));
+    }
+
+    #[test]
+    fn unsupported_chars_have_no_glyph() {
+        assert!(glyph_bitmap('%').is_none());
+        assert!(glyph_bitmap('é').is_none());
+    }
+}
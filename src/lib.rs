@@ -1,11 +1,47 @@
+mod caption;
+mod coco;
 mod dataset;
+mod distribution;
 mod entry;
+mod env;
+mod export;
+mod font;
+mod npy;
+mod obstacle;
+mod physics;
+mod placement;
 mod py;
+mod scene_graph;
 mod shape;
+mod split;
+mod sprite;
+mod tar;
+mod tracking;
+mod video;
+mod vqa;
 
-pub use dataset::Dataset;
+pub use caption::{Captioner, Verbosity};
+pub use coco::{CocoExporter, Segmentation};
+pub use dataset::{Dataset, GenerationError};
+pub use distribution::Distribution;
+pub use entry::Entry;
+pub use env::{Action, Environment, Step, Task, NUM_DISCRETE_ACTIONS};
+pub use export::{ArrayFormat, NpyExporter, SampleEncoding, TarExporter};
 use ndarray::Array3;
-pub use shape::ShapeType;
+pub use npy::{npy_bytes, write_npy, NpyElement, NpzWriter};
+pub use obstacle::{Obstacle, ObstacleShape, ObstacleType};
+pub use physics::{Boundary, Physics};
+pub use placement::{Placement, PlacementError};
+pub use scene_graph::{SceneEdge, SceneGraph, SceneNode, SpatialRelation};
+pub use shape::{
+    Anchor, Color, FillStyle, Shape, ShapeOverrides, ShapeType, Texture, VelocityDistribution,
+};
+pub use split::{Combination, Split, SplitError, SplitMode};
+pub use sprite::Sprite;
+pub use tar::TarWriter;
+pub use tracking::{save_tracks, write_json, write_mot, TrackAnnotation, TrackFormat};
+pub use video::{save_video, write_apng, write_gif, write_raw, write_y4m, VideoFormat};
+pub use vqa::{Question, QuestionGenerator, QuestionType};
 
 use image::{ImageBuffer, Rgb, RgbImage};
 
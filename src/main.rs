@@ -1,26 +1,125 @@
 // use ndarray::prelude::*;
 
-use funnyshapes::ndarray_to_image;
-use funnyshapes::{Dataset, ShapeType};
+use std::fs;
+use std::path::PathBuf;
+
+use funnyshapes::{ndarray_to_image, save_tracks, save_video};
+use funnyshapes::{
+    ArrayFormat, CocoExporter, Dataset, NpyExporter, SampleEncoding, Segmentation, ShapeType,
+    TarExporter, TrackFormat, VideoFormat,
+};
 
 use indicatif::ProgressBar;
-use std::time::Instant;
 
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
 struct Config {
-    #[arg(long, short)]
-    num_to_generate: u32,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Writes random images as PNG files.
+    GenerateImages {
+        #[arg(long, short)]
+        num_to_generate: u32,
+
+        #[arg(long, short)]
+        size: u16,
+
+        #[arg(long, short, default_value = "outputs")]
+        output_dir: PathBuf,
+    },
+    /// Writes random videos, as numbered PNG frames or animations.
+    GenerateVideos {
+        #[arg(long, short)]
+        num_to_generate: u32,
+
+        #[arg(long, short)]
+        size: u16,
+
+        #[arg(long, default_value_t = 100)]
+        num_frames: usize,
+
+        #[arg(long, default_value_t = 0.02)]
+        step_size: f64,
+
+        /// Save whole videos in this format, rather than one PNG per frame.
+        #[arg(long, value_enum)]
+        format: Option<VideoFormat>,
+
+        /// How long each frame is shown, except in raw videos.
+        #[arg(long, default_value_t = 50)]
+        frame_delay_ms: u16,
+
+        /// Also write each video's ground truth tracks next to it.
+        #[arg(long, value_enum)]
+        tracks: Option<TrackFormat>,
+
+        #[arg(long, short, default_value = "outputs")]
+        output_dir: PathBuf,
+    },
+    /// Pre-generates a dataset of images, or videos if `--num-frames` is set,
+    /// with instance masks, as sharded arrays.
+    Export {
+        #[arg(long, short)]
+        num_to_generate: usize,
+
+        #[arg(long, short)]
+        size: u16,
 
-    #[arg(long, short)]
-    size: u32,
+        #[arg(long)]
+        num_frames: Option<usize>,
+
+        #[arg(long, default_value_t = 0.02)]
+        step_size: f64,
+
+        #[arg(long, value_enum, default_value_t = ExportFormat::Npy)]
+        format: ExportFormat,
+
+        /// Number of samples per shard, for npy and npz.
+        #[arg(
+            long,
+            default_value_t = 1000,
+            value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        )]
+        shard_size: usize,
+
+        /// Target size of each shard in megabytes, for tar.
+        #[arg(long, default_value_t = 1024)]
+        shard_mb: u64,
+
+        /// Store samples in tar shards as raw .npy tensors rather than PNGs.
+        #[arg(long)]
+        raw: bool,
+
+        /// Write COCO segmentations as polygons rather than RLE.
+        #[arg(long)]
+        polygons: bool,
+
+        #[arg(long, short, default_value = "outputs")]
+        output_dir: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    /// Separate .npy files for images and masks.
+    Npy,
+    Npz,
+    /// WebDataset style tar shards, with JSON metadata per sample.
+    Tar,
+    /// COCO detection dataset of images.
+    Coco,
 }
 
 fn main() {
-    // let args = Config::parse();
+    let args = Config::parse();
     let dataset = Dataset::new()
         .shape_types(vec![ShapeType::Square, ShapeType::Circle])
         // .shape_types(vec![ShapeType::Circle])
@@ -31,43 +130,149 @@ fn main() {
         .add_color(0, 255, 255)
         .add_color(255, 0, 255)
         .size_range(0.03, 0.15)
-        .position_range(0.0, 0.8) // TODO: offset real random range by per shape size (pos_upper - actual_size)
+        .position_range(0.0, 1.0)
         .velocity_range(-0.8, 0.8)
         .num_shapes_range(20, 20);
 
-    println!("{:#?}", dataset);
+    match args.command {
+        Command::GenerateImages {
+            num_to_generate,
+            size,
+            output_dir,
+        } => {
+            fs::create_dir_all(&output_dir).expect("Failed to create output directory!");
+
+            let pb = ProgressBar::new(num_to_generate as u64);
+            for i in 0..num_to_generate {
+                let array = dataset.get_random_image_array(size);
+                let img = ndarray_to_image(array, size as u32);
+                img.save(output_dir.join(format!("image_{i:06}.png")))
+                    .expect("Failed to save image!");
+                pb.inc(1);
+            }
+            pb.finish_with_message("Done");
+        }
+        Command::GenerateVideos {
+            num_to_generate,
+            size,
+            num_frames,
+            step_size,
+            format,
+            frame_delay_ms,
+            tracks,
+            output_dir,
+        } => {
+            fs::create_dir_all(&output_dir).expect("Failed to create output directory!");
+
+            let pb = ProgressBar::new(num_to_generate as u64);
+            for v in 0..num_to_generate {
+                let video = match tracks {
+                    Some(track_format) => {
+                        let (video, annotations) =
+                            dataset.get_random_video_with_tracks(num_frames, size, step_size);
+                        let extension = track_format.extension();
+                        save_tracks(
+                            output_dir.join(format!("video_{v:06}.{extension}")),
+                            &annotations,
+                        )
+                        .expect("Failed to save tracks!");
+                        video
+                    }
+                    None => dataset.get_random_video_array(num_frames, size, step_size),
+                };
+
+                match format {
+                    None => {
+                        for (i, frame) in video.outer_iter().enumerate() {
+                            let img = ndarray_to_image(frame.to_owned(), size as u32);
+                            img.save(output_dir.join(format!("video_{v:06}_{i:06}.png")))
+                                .expect("Failed to save image!");
+                        }
+                    }
+                    Some(format) => save_video(
+                        output_dir.join(format!("video_{v:06}.{}", format.extension())),
+                        &video,
+                        frame_delay_ms,
+                    )
+                    .expect("Failed to save video!"),
+                }
+                pb.inc(1);
+            }
+            pb.finish_with_message("Done");
+        }
+        Command::Export {
+            num_to_generate,
+            size,
+            num_frames,
+            step_size,
+            format,
+            shard_size,
+            shard_mb,
+            raw,
+            polygons,
+            output_dir,
+        } => {
+            if matches!(format, ExportFormat::Coco) && num_frames.is_some() {
+                Config::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "COCO export only supports images, not --num-frames",
+                    )
+                    .exit();
+            }
+            fs::create_dir_all(&output_dir).expect("Failed to create output directory!");
 
-    let mut random_entry = dataset.generate_random_entry();
+            match format {
+                ExportFormat::Npy | ExportFormat::Npz => {
+                    let format = match format {
+                        ExportFormat::Npz => ArrayFormat::Npz,
+                        _ => ArrayFormat::Npy,
+                    };
+                    let mut exporter = NpyExporter::new(size).format(format).shard_size(shard_size);
+                    if let Some(num_frames) = num_frames {
+                        exporter = exporter.videos(num_frames, step_size);
+                    }
 
-    let num_frames = 1000;
-    let size = 1024;
-    let step_size = 0.02;
-    let pb = ProgressBar::new(num_frames);
-    for i in 0..num_frames {
-        let array = random_entry.render_entry(size);
-        let img = ndarray_to_image(array, size as u32);
-        img.save(format!("outputs/test_{i:06}.png"))
-            .expect("Failed to save image!");
+                    let num_shards = exporter.num_shards(num_to_generate);
+                    let pb = ProgressBar::new(num_shards as u64);
+                    for shard in 0..num_shards {
+                        exporter
+                            .write_shard(&dataset, &output_dir, shard, num_to_generate)
+                            .expect("Failed to write shard!");
+                        pb.inc(1);
+                    }
+                    pb.finish_with_message("Done");
+                }
+                ExportFormat::Tar => {
+                    let encoding = if raw {
+                        SampleEncoding::Npy
+                    } else {
+                        SampleEncoding::Png
+                    };
+                    let mut exporter = TarExporter::new(size)
+                        .encoding(encoding)
+                        .shard_bytes(shard_mb << 20);
+                    if let Some(num_frames) = num_frames {
+                        exporter = exporter.videos(num_frames, step_size);
+                    }
 
-        random_entry.step_entry(step_size);
-        pb.inc(1);
+                    let paths = exporter
+                        .export(&dataset, &output_dir, num_to_generate)
+                        .expect("Failed to write shards!");
+                    println!("Wrote {} shards", paths.len());
+                }
+                ExportFormat::Coco => {
+                    let segmentation = if polygons {
+                        Segmentation::Polygon
+                    } else {
+                        Segmentation::Rle
+                    };
+                    CocoExporter::new(size)
+                        .segmentation(segmentation)
+                        .export(&dataset, &output_dir, num_to_generate)
+                        .expect("Failed to write COCO dataset!");
+                }
+            }
+        }
     }
-    pb.finish_with_message("Done");
-
-    // let start_time = Instant::now();
-    // let size = args.size;
-    // // 2650ms with circle, 183ms without
-    // // improved to 531ms with rayon
-    // for i in 0..args.num_to_generate {
-    //     let random_entry = dataset.generate_random_entry();
-
-    //     let array = random_entry.render_entry(size as u16);
-    //     let img = ndarray_to_image(array, size);
-    //     img.save(format!("outputs/test_{i}.png"))
-    //         .expect("Failed to save image!");
-
-    //     println!("{} / {} complete.", i + 1, args.num_to_generate);
-    // }
-    // let duration = start_time.elapsed();
-    // println!("Time taken: {duration:?}");
 }
@@ -0,0 +1,236 @@
+use std::io::{self, Write};
+
+use ndarray::{ArrayBase, Data, Dimension};
+
+/// Element types that can be written to `.npy` files.
+pub trait NpyElement: Copy {
+    /// NumPy type string of the element, e.g. `<f8`.
+    const DESCR: &'static str;
+
+    fn extend_le_bytes(self, bytes: &mut Vec<u8>);
+}
+
+macro_rules! impl_npy_element {
+    ($t:ty, $descr:expr) => {
+        impl NpyElement for $t {
+            const DESCR: &'static str = $descr;
+
+            fn extend_le_bytes(self, bytes: &mut Vec<u8>) {
+                bytes.extend_from_slice(&self.to_le_bytes());
+            }
+        }
+    };
+}
+
+impl_npy_element!(u8, "|u1");
+impl_npy_element!(i64, "<i8");
+impl_npy_element!(f32, "<f4");
+impl_npy_element!(f64, "<f8");
+
+/// An array encoded as a version 1.0 `.npy` file, in C order.
+pub fn npy_bytes<A, S, D>(array: &ArrayBase<S, D>) -> Vec<u8>
+where
+    A: NpyElement,
+    S: Data<Elem = A>,
+    D: Dimension,
+{
+    let shape = match array.shape() {
+        [n] => format!("({n},)"),
+        shape => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {shape}, }}",
+        A::DESCR
+    );
+    // pad so the data starts on a 64 byte boundary, as NumPy does
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(10 + header.len() + array.len() * 8);
+    bytes.extend_from_slice(b"\x93NUMPY\x01\x00");
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for &value in array.iter() {
+        value.extend_le_bytes(&mut bytes);
+    }
+    bytes
+}
+
+pub fn write_npy<A, S, D, W>(mut writer: W, array: &ArrayBase<S, D>) -> io::Result<()>
+where
+    A: NpyElement,
+    S: Data<Elem = A>,
+    D: Dimension,
+    W: Write,
+{
+    writer.write_all(&npy_bytes(array))?;
+    writer.flush()
+}
+
+/// Date 1980-01-01 in MS-DOS format, the earliest a zip file can hold.
+const DOS_EPOCH: u16 = 0x21;
+
+/// Writes `.npz` archives: uncompressed zips of `.npy` files, as written by
+/// `numpy.savez`.
+pub struct NpzWriter<W: Write> {
+    writer: W,
+    offset: u32,
+    central_directory: Vec<u8>,
+    num_entries: u16,
+}
+
+impl<W: Write> NpzWriter<W> {
+    pub fn new(writer: W) -> Self {
+        NpzWriter {
+            writer,
+            offset: 0,
+            central_directory: Vec::new(),
+            num_entries: 0,
+        }
+    }
+
+    /// Adds `array` to the archive, loaded by NumPy under `name`.
+    pub fn add_array<A, S, D>(&mut self, name: &str, array: &ArrayBase<S, D>) -> io::Result<()>
+    where
+        A: NpyElement,
+        S: Data<Elem = A>,
+        D: Dimension,
+    {
+        let data = npy_bytes(array);
+        let file_name = format!("{name}.npy");
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "npz archive too large");
+        let data_len = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let crc = crc32fast::hash(&data);
+
+        // fields shared by the local header and the central directory entry,
+        // from "version needed to extract" to "extra field length"
+        let mut fields = Vec::with_capacity(26);
+        fields.extend_from_slice(&20u16.to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes()); // flags
+        fields.extend_from_slice(&0u16.to_le_bytes()); // stored, no compression
+        fields.extend_from_slice(&0u16.to_le_bytes()); // time
+        fields.extend_from_slice(&DOS_EPOCH.to_le_bytes());
+        fields.extend_from_slice(&crc.to_le_bytes());
+        fields.extend_from_slice(&data_len.to_le_bytes());
+        fields.extend_from_slice(&data_len.to_le_bytes());
+        fields.extend_from_slice(&(file_name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+
+        self.writer.write_all(&0x04034b50u32.to_le_bytes())?;
+        self.writer.write_all(&fields)?;
+        self.writer.write_all(file_name.as_bytes())?;
+        self.writer.write_all(&data)?;
+
+        let directory = &mut self.central_directory;
+        directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        directory.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        directory.extend_from_slice(&fields);
+        directory.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        directory.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        directory.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        directory.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        directory.extend_from_slice(&self.offset.to_le_bytes());
+        directory.extend_from_slice(file_name.as_bytes());
+
+        let entry_len = 30 + file_name.len() as u32 + data_len;
+        self.offset = self.offset.checked_add(entry_len).ok_or_else(too_large)?;
+        self.num_entries += 1;
+        Ok(())
+    }
+
+    /// Writes the zip central directory, returning the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&self.central_directory)?;
+
+        self.writer.write_all(&0x06054b50u32.to_le_bytes())?;
+        self.writer.write_all(&0u16.to_le_bytes())?; // disk number
+        self.writer.write_all(&0u16.to_le_bytes())?; // disk with the directory
+        self.writer.write_all(&self.num_entries.to_le_bytes())?;
+        self.writer.write_all(&self.num_entries.to_le_bytes())?;
+        self.writer
+            .write_all(&(self.central_directory.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&self.offset.to_le_bytes())?;
+        self.writer.write_all(&0u16.to_le_bytes())?; // comment length
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{arr1, Array2};
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn npy_headers_follow_the_format() {
+        let bytes = npy_bytes(&Array2::<u8>::from_shape_fn((2, 3), |(y, x)| {
+            (y * 3 + x) as u8
+        }));
+
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16_at(&bytes, 8) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+
+        let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '|u1', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(&bytes[10 + header_len..], [0, 1, 2, 3, 4, 5]);
+
+        let bytes = npy_bytes(&arr1(&[1.5f64]));
+        let header = String::from_utf8_lossy(&bytes[10..]);
+        assert!(header.contains("'descr': '<f8'") && header.contains("'shape': (1,)"));
+    }
+
+    #[test]
+    fn npz_directory_points_at_entries() {
+        let arrays = [("a", arr1(&[1u8, 2])), ("bb", arr1(&[3u8]))];
+        let mut npz = NpzWriter::new(Vec::new());
+        for (name, array) in &arrays {
+            npz.add_array(name, array).unwrap();
+        }
+        let zip = npz.finish().unwrap();
+
+        // end of central directory record
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(&zip, end), 0x06054b50);
+        assert_eq!(u16_at(&zip, end + 10), 2);
+        let directory_len = u32_at(&zip, end + 12) as usize;
+        let mut entry = u32_at(&zip, end + 16) as usize;
+        assert_eq!(entry + directory_len, end);
+
+        for (name, array) in &arrays {
+            assert_eq!(u32_at(&zip, entry), 0x02014b50);
+            let name_len = u16_at(&zip, entry + 28) as usize;
+            let file_name = &zip[entry + 46..entry + 46 + name_len];
+            assert_eq!(file_name, format!("{name}.npy").as_bytes());
+
+            // the local header the directory entry points at
+            let local = u32_at(&zip, entry + 42) as usize;
+            assert_eq!(u32_at(&zip, local), 0x04034b50);
+            assert_eq!(&zip[local + 30..local + 30 + name_len], file_name);
+            let data_len = u32_at(&zip, local + 18) as usize;
+            let data = &zip[local + 30 + name_len..local + 30 + name_len + data_len];
+            assert_eq!(data, npy_bytes(array));
+            assert_eq!(u32_at(&zip, local + 14), crc32fast::hash(data));
+
+            entry += 46 + name_len;
+        }
+    }
+}
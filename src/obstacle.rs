@@ -0,0 +1,171 @@
+use rand::Rng;
+
+use super::shape::{Color, NewRandom1, Size};
+use super::RangeOrSingle;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ObstacleType {
+    Rectangle,
+    Segment,
+}
+
+/// Geometry of an obstacle, in the same [0, 1] image units as shapes.
+#[derive(Debug, Copy, Clone)]
+pub enum ObstacleShape {
+    Rectangle {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+    Segment {
+        start: (f64, f64),
+        end: (f64, f64),
+        thickness: f64,
+    },
+}
+
+/// An immovable object that shapes bounce off.
+#[derive(Debug, Copy, Clone)]
+pub struct Obstacle {
+    pub shape: ObstacleShape,
+    pub color: Color,
+}
+
+impl Obstacle {
+    /// A rectangle with its top-left corner at (x, y). Panics unless `width`
+    /// and `height` are positive.
+    pub fn rectangle(x: f64, y: f64, width: f64, height: f64) -> Self {
+        if width <= 0.0 || height <= 0.0 {
+            panic!("Obstacle width and height must be positive. Got {width} and {height}");
+        }
+
+        Obstacle {
+            shape: ObstacleShape::Rectangle {
+                x,
+                y,
+                width,
+                height,
+            },
+            color: Color::new(0x80, 0x80, 0x80),
+        }
+    }
+
+    /// A segment from (x1, y1) to (x2, y2). Panics unless `thickness` is
+    /// positive.
+    pub fn segment(x1: f64, y1: f64, x2: f64, y2: f64, thickness: f64) -> Self {
+        if thickness <= 0.0 {
+            panic!("Obstacle thickness must be positive. Got {thickness}");
+        }
+
+        Obstacle {
+            shape: ObstacleShape::Segment {
+                start: (x1, y1),
+                end: (x2, y2),
+                thickness,
+            },
+            color: Color::new(0x80, 0x80, 0x80),
+        }
+    }
+
+    pub fn color(mut self, r: u8, g: u8, b: u8) -> Self {
+        self.color = Color::new(r, g, b);
+        self
+    }
+
+    /// A random obstacle inside the image. Rectangles take their width and
+    /// height from `size_range`, segments their length, with sizes above 1
+    /// clamped to 1.
+    pub fn new_random(obstacle_type: ObstacleType, size_range: &RangeOrSingle<f64>) -> Self {
+        let mut rng = rand::thread_rng();
+
+        match obstacle_type {
+            ObstacleType::Rectangle => {
+                let width = Size::new_from_range_or_single(size_range).0.min(1.0);
+                let height = Size::new_from_range_or_single(size_range).0.min(1.0);
+                let x = rng.gen_range(0.0..=(1.0 - width).max(0.0));
+                let y = rng.gen_range(0.0..=(1.0 - height).max(0.0));
+                Obstacle::rectangle(x, y, width, height)
+            }
+            ObstacleType::Segment => {
+                let length = Size::new_from_range_or_single(size_range).0.min(1.0);
+                let angle = rng.gen_range(0.0..std::f64::consts::PI);
+                let (dx, dy) = (length * angle.cos(), length * angle.sin());
+
+                // keep both ends inside the image
+                let x_high = (1.0 - dx.max(0.0)).max(0.0);
+                let x1 = rng.gen_range((-dx).max(0.0).min(x_high)..=x_high);
+                let y1 = rng.gen_range(0.0..=(1.0 - dy).max(0.0));
+                Obstacle::segment(x1, y1, x1 + dx, y1 + dy, 0.01)
+            }
+        }
+    }
+
+    /// Axis-aligned bounds as (x1, y1, x2, y2).
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        match self.shape {
+            ObstacleShape::Rectangle {
+                x,
+                y,
+                width,
+                height,
+            } => (x, y, x + width, y + height),
+            ObstacleShape::Segment {
+                start,
+                end,
+                thickness,
+            } => {
+                let r = thickness / 2.;
+                (
+                    start.0.min(end.0) - r,
+                    start.1.min(end.1) - r,
+                    start.0.max(end.0) + r,
+                    start.1.max(end.1) + r,
+                )
+            }
+        }
+    }
+
+    /// Smallest extent of the obstacle, which shapes must not jump over.
+    pub fn thickness(&self) -> f64 {
+        match self.shape {
+            ObstacleShape::Rectangle { width, height, .. } => width.min(height),
+            ObstacleShape::Segment { thickness, .. } => thickness,
+        }
+    }
+
+    pub fn covers(&self, x: f64, y: f64) -> bool {
+        match self.shape {
+            ObstacleShape::Rectangle { .. } => {
+                let (x1, y1, x2, y2) = self.bounds();
+                (x1..=x2).contains(&x) && (y1..=y2).contains(&y)
+            }
+            ObstacleShape::Segment {
+                start,
+                end,
+                thickness,
+            } => {
+                let (cx, cy) = closest_point_on_segment(start, end, (x, y));
+                let (dx, dy) = (x - cx, y - cy);
+                (dx * dx + dy * dy).sqrt() <= thickness / 2.
+            }
+        }
+    }
+}
+
+/// The point of the segment from `start` to `end` closest to `point`.
+pub fn closest_point_on_segment(
+    start: (f64, f64),
+    end: (f64, f64),
+    point: (f64, f64),
+) -> (f64, f64) {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length_squared = dx * dx + dy * dy;
+    if length_squared == 0.0 {
+        return start;
+    }
+
+    let t =
+        (((point.0 - start.0) * dx + (point.1 - start.1) * dy) / length_squared).clamp(0.0, 1.0);
+    (start.0 + t * dx, start.1 + t * dy)
+}
@@ -0,0 +1,62 @@
+/// Forces applied to every shape of an `Entry` on each step. Positions are in
+/// image units, so gravity is in image units per unit time squared, with
+/// positive y pointing down the image.
+#[derive(Debug, Clone, Copy)]
+pub struct Physics {
+    pub gravity: (f64, f64),
+    /// Linear drag coefficient; the deceleration of a shape is
+    /// `drag * velocity / mass`.
+    pub drag: f64,
+    /// Fraction of velocity kept when bouncing off a wall. 1 is perfectly
+    /// elastic, 0 stops the shape against the wall.
+    pub restitution: f64,
+}
+
+impl Physics {
+    pub fn new() -> Self {
+        Physics {
+            gravity: (0.0, 0.0),
+            drag: 0.0,
+            restitution: 1.0,
+        }
+    }
+
+    pub fn gravity(mut self, x: f64, y: f64) -> Self {
+        self.gravity = (x, y);
+        self
+    }
+
+    pub fn drag(mut self, drag: f64) -> Self {
+        self.drag = drag;
+        self
+    }
+
+    pub fn restitution(mut self, restitution: f64) -> Self {
+        if !(0.0..=1.0).contains(&restitution) {
+            panic!("Restitution must be in range [0, 1]. Got {restitution}");
+        }
+
+        self.restitution = restitution;
+        self
+    }
+}
+
+impl Default for Physics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What happens to shapes reaching the edge of the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Boundary {
+    /// Bounce off the edges.
+    Reflect,
+    /// Leave one edge and re-enter from the opposite one, drawn split across
+    /// both edges in between.
+    Wrap,
+    /// Carry on out of the frame.
+    Open,
+    /// Stop dead at the edge.
+    Absorb,
+}
@@ -0,0 +1,256 @@
+use std::error::Error;
+use std::fmt;
+
+use super::obstacle::{closest_point_on_segment, Obstacle, ObstacleShape};
+use super::shape::Shape;
+
+/// Constraints on where the shapes of a random entry start, enforced by
+/// rejection sampling. Box based policies use each shape's square bounding
+/// box, in image units. Whatever the policy, shapes never start on an
+/// obstacle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Placement {
+    /// Positions are drawn independently, so shapes may overlap arbitrarily.
+    Independent,
+    /// Bounding boxes of shapes never overlap.
+    NoOverlap,
+    /// Bounding boxes of shapes are at least this far apart.
+    MinSeparation(f64),
+    /// At most this fraction of each shape's bounding box is covered by the
+    /// boxes of shapes drawn over it, counting overlaps between those shapes
+    /// twice.
+    MaxOcclusion(f64),
+    /// At least this fraction of each shape's pixels are visible in the first
+    /// frame, neither hidden by other shapes nor out of frame.
+    MinVisibility(f64),
+}
+
+/// Returned when no placement satisfying a policy was found within the retry
+/// budget, which usually means the scene is too crowded for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacementError {
+    pub placement: Placement,
+    pub tries: usize,
+}
+
+impl fmt::Display for PlacementError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Failed to place shapes with {:?} after {} tries",
+            self.placement, self.tries
+        )
+    }
+}
+
+impl Error for PlacementError {}
+
+/// Axis-aligned box as (x1, y1, x2, y2).
+pub(crate) type BoundingBox = (f64, f64, f64, f64);
+
+/// The square bounding box of a shape.
+pub(crate) fn shape_box(shape: &Shape) -> BoundingBox {
+    let (x, y, size) = (shape.position.0, shape.position.1, shape.size.0);
+    (x, y, x + size, y + size)
+}
+
+/// The center of a shape's bounding box.
+pub(crate) fn shape_center(shape: &Shape) -> (f64, f64) {
+    let (x1, y1, x2, y2) = shape_box(shape);
+    ((x1 + x2) / 2., (y1 + y2) / 2.)
+}
+
+pub(crate) fn box_area((x1, y1, x2, y2): BoundingBox) -> f64 {
+    (x2 - x1) * (y2 - y1)
+}
+
+pub(crate) fn overlap_area(a: BoundingBox, b: BoundingBox) -> f64 {
+    let width = a.2.min(b.2) - a.0.max(b.0);
+    let height = a.3.min(b.3) - a.1.max(b.1);
+    width.max(0.) * height.max(0.)
+}
+
+pub(crate) fn box_distance(a: BoundingBox, b: BoundingBox) -> f64 {
+    let dx = (a.0.max(b.0) - a.2.min(b.2)).max(0.);
+    let dy = (a.1.max(b.1) - a.3.min(b.3)).max(0.);
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// Whether a shape with box `candidate` overlaps `obstacle`, with the same
+/// geometry shapes collide with it.
+pub(crate) fn overlaps_obstacle(candidate: BoundingBox, obstacle: &Obstacle) -> bool {
+    match obstacle.shape {
+        ObstacleShape::Rectangle { .. } => overlap_area(candidate, obstacle.bounds()) > 0.,
+        ObstacleShape::Segment {
+            start,
+            end,
+            thickness,
+        } => {
+            // shapes collide with segments as their inscribed circle
+            let radius = (candidate.2 - candidate.0) / 2.;
+            let center = (candidate.0 + radius, candidate.1 + radius);
+            let (cx, cy) = closest_point_on_segment(start, end, center);
+            let (dx, dy) = (center.0 - cx, center.1 - cy);
+            (dx * dx + dy * dy).sqrt() < radius + thickness / 2.
+        }
+    }
+}
+
+impl Placement {
+    /// Whether a shape with box `candidate` drawn over shapes with boxes
+    /// `placed` satisfies the policy, given the area of each placed box
+    /// already covered by shapes drawn over it. Policies checked on the whole
+    /// scene always accept.
+    pub(crate) fn accepts(
+        &self,
+        candidate: BoundingBox,
+        placed: &[BoundingBox],
+        occluded: &[f64],
+    ) -> bool {
+        match *self {
+            Placement::Independent | Placement::MinVisibility(_) => true,
+            Placement::NoOverlap => placed
+                .iter()
+                .all(|&other| overlap_area(candidate, other) <= 0.),
+            Placement::MinSeparation(distance) => placed
+                .iter()
+                .all(|&other| box_distance(candidate, other) >= distance),
+            Placement::MaxOcclusion(fraction) => {
+                placed.iter().zip(occluded).all(|(&other, &covered)| {
+                    covered + overlap_area(candidate, other) <= fraction * box_area(other)
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Anchor, Dataset, Distribution, Entry, GenerationError, ShapeType};
+
+    fn dataset(placement: Placement) -> Dataset {
+        Dataset::new()
+            .shape_types(vec![ShapeType::Square])
+            .add_color(255, 0, 0)
+            .size(0.2)
+            .position_range(0.0, 0.8)
+            .num_shapes(5)
+            .placement(placement)
+    }
+
+    #[test]
+    fn no_overlap_separates_boxes() {
+        for _ in 0..20 {
+            let entry = dataset(Placement::NoOverlap).generate_random_entry();
+            let boxes: Vec<BoundingBox> = entry.shapes().iter().map(shape_box).collect();
+
+            for (i, &a) in boxes.iter().enumerate() {
+                for &b in &boxes[i + 1..] {
+                    assert!(overlap_area(a, b) <= 0.);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn min_separation_keeps_boxes_apart() {
+        for _ in 0..20 {
+            let entry = dataset(Placement::MinSeparation(0.05)).generate_random_entry();
+            let boxes: Vec<BoundingBox> = entry.shapes().iter().map(shape_box).collect();
+
+            for (i, &a) in boxes.iter().enumerate() {
+                for &b in &boxes[i + 1..] {
+                    assert!(box_distance(a, b) >= 0.05);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn max_occlusion_bounds_covered_area() {
+        for _ in 0..20 {
+            let entry = dataset(Placement::MaxOcclusion(0.25)).generate_random_entry();
+            let boxes: Vec<BoundingBox> = entry.shapes().iter().map(shape_box).collect();
+
+            // shapes are drawn in order, so later ones cover earlier ones
+            for (i, &a) in boxes.iter().enumerate() {
+                let covered: f64 = boxes[i + 1..].iter().map(|&b| overlap_area(a, b)).sum();
+                assert!(covered <= 0.25 * box_area(a) + 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn min_visibility_keeps_shapes_visible() {
+        for _ in 0..20 {
+            let entry = dataset(Placement::MinVisibility(0.5)).generate_random_entry();
+            let tracks = entry.track_annotations(0, 64);
+
+            assert_eq!(tracks.len(), 5);
+            assert!(tracks.iter().all(|track| track.visibility >= 0.5));
+        }
+    }
+
+    #[test]
+    fn shapes_start_in_frame_and_off_obstacles() {
+        let obstacles = [
+            Obstacle::rectangle(0.0, 0.3, 0.4, 0.4),
+            Obstacle::segment(0.6, 0.0, 0.6, 1.0, 0.05),
+        ];
+        let dataset = obstacles.iter().fold(
+            dataset(Placement::NoOverlap).num_shapes(3),
+            |dataset, &o| dataset.add_obstacle(o),
+        );
+
+        for _ in 0..200 {
+            let entry = dataset.generate_random_entry();
+            let boxes: Vec<BoundingBox> = entry.shapes().iter().map(shape_box).collect();
+
+            for (i, &a) in boxes.iter().enumerate() {
+                assert!(a.0 >= 0. && a.1 >= 0. && a.2 <= 1. && a.3 <= 1.);
+                assert!(!obstacles.iter().any(|o| overlaps_obstacle(a, o)));
+                for &b in &boxes[i + 1..] {
+                    assert!(overlap_area(a, b) <= 0.);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn acceptable_positions_are_kept() {
+        let shapes = [(0.1, 0.1), (0.15, 0.15), (0.6, 0.6)]
+            .into_iter()
+            .map(|(x, y)| Shape::new(&ShapeType::Square).size(0.2).position(x, y))
+            .collect();
+        let mut entry = Entry::new(shapes);
+        let corners = |entry: &Entry| -> Vec<(f64, f64)> {
+            entry.positions().iter().map(|p| (p.0, p.1)).collect()
+        };
+        let positions = corners(&entry);
+
+        let distribution = Distribution::uniform(0.0, 1.0);
+        entry
+            .place_shapes(&distribution, Anchor::TopLeft, Placement::Independent, 10)
+            .unwrap();
+        assert_eq!(corners(&entry), positions);
+
+        // the first shape stays and the second, overlapping it, moves
+        entry
+            .place_shapes(&distribution, Anchor::TopLeft, Placement::NoOverlap, 1000)
+            .unwrap();
+        let moved = corners(&entry);
+        assert_eq!(moved[0], positions[0]);
+        assert_ne!(moved[1], positions[1]);
+    }
+
+    #[test]
+    fn infeasible_placement_is_an_error() {
+        let dataset = dataset(Placement::MinSeparation(0.9)).max_placement_tries(50);
+        let error = dataset.try_generate_random_entry().unwrap_err();
+        assert!(matches!(
+            error,
+            GenerationError::Placement(PlacementError { tries: 50, .. })
+        ));
+    }
+}
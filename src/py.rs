@@ -1,9 +1,20 @@
+// pyo3 0.20 expands `#[pymethods]` into non-local impls
+#![allow(non_local_definitions)]
+
 use ndarray::parallel::prelude::IntoParallelIterator;
-use numpy::{IntoPyArray, PyArray3, PyArray4, PyArray5, ToPyArray};
-use pyo3::{pyclass, pymethods, pymodule, types::PyModule, PyResult, Python};
+use numpy::{IntoPyArray, PyArray3, PyArray4, PyArray5, PyReadonlyArray4, ToPyArray};
+use pyo3::exceptions::PyValueError;
+use pyo3::types::{PyDict, PyList, PyModule};
+use pyo3::{pyclass, pymethods, pymodule, PyAny, PyObject, PyResult, Python, ToPyObject};
 use rayon::prelude::*;
+use serde::Serialize;
 
-use crate::{Dataset, ShapeType};
+use crate::{
+    Action, Anchor, Boundary, Captioner, Color, Combination, Dataset, Distribution, Entry,
+    Environment, FillStyle, Obstacle, ObstacleType, Placement, QuestionGenerator, QuestionType,
+    SceneGraph, ShapeOverrides, ShapeType, Split, SplitMode, Sprite, Task, Texture,
+    VelocityDistribution, Verbosity, NUM_DISCRETE_ACTIONS,
+};
 use ndarray::{stack, Array3, Array4, ArrayView3, ArrayView4, Axis};
 
 #[pymodule]
@@ -40,109 +51,1076 @@ fn funnyshapes<'py>(_py: Python<'py>, m: &'py PyModule) -> PyResult<()> {
         array.into_pyarray(py)
     }
 
-    #[pyclass(name = "FunnyShapesDataset")]
-    struct PyDataset {
-        inner: Dataset,
+    /// Saves a (frames, 3, size, size) video as a GIF, APNG, Y4M or raw RGB
+    /// stream, depending on the extension of `path`.
+    #[pyfn(m)]
+    #[pyo3(name = "save_video")]
+    #[pyo3(signature = (path, video, frame_delay_ms = 50))]
+    fn save_video_py(
+        path: &str,
+        video: PyReadonlyArray4<f64>,
+        frame_delay_ms: u16,
+    ) -> PyResult<()> {
+        let video = video.as_array().to_owned();
+        crate::video::check_video(&video).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        crate::save_video(path, &video, frame_delay_ms)?;
+        Ok(())
+    }
+
+    m.add_class::<PyDataset>()?;
+    m.add_class::<PyEnvironment>()?;
+    Ok(())
+}
+
+/// Parses shape type names from Python. "digits" and "letters" expand to
+/// every glyph in that group, and "sprites" to every loaded sprite.
+fn parse_shape_types(names: Vec<String>, num_sprites: usize) -> PyResult<Vec<ShapeType>> {
+    let mut shape_types = Vec::with_capacity(names.len());
+    for name in names {
+        match name.as_str() {
+            "digits" => shape_types.extend(ShapeType::digits()),
+            "letters" => shape_types.extend(ShapeType::letters()),
+            "sprites" if num_sprites == 0 => {
+                return Err(PyValueError::new_err("No sprites loaded for 'sprites'"))
+            }
+            "sprites" => shape_types.extend((0..num_sprites).map(ShapeType::Sprite)),
+            _ => match name.parse().map_err(PyValueError::new_err)? {
+                ShapeType::Sprite(i) if i >= num_sprites => {
+                    return Err(PyValueError::new_err(format!(
+                        "No sprite {i}, only {num_sprites} loaded"
+                    )))
+                }
+                shape_type => shape_types.push(shape_type),
+            },
+        }
+    }
+    Ok(shape_types)
+}
+
+/// Parses held out combinations from Python dicts with any of a
+/// "shape_type" named as in the constructor, an (r, g, b) "color" and a
+/// (low, high) "size" bucket.
+fn parse_combinations(
+    combinations: Vec<&PyDict>,
+    num_sprites: usize,
+) -> PyResult<Vec<Combination>> {
+    combinations
+        .into_iter()
+        .map(|dict| {
+            let mut combination = Combination::new();
+            for (key, value) in dict.iter() {
+                match key.extract::<&str>()? {
+                    "shape_type" => {
+                        let name: String = value.extract()?;
+                        match parse_shape_types(vec![name.clone()], num_sprites)?[..] {
+                            [shape_type] => combination = combination.shape_type(shape_type),
+                            _ => {
+                                return Err(PyValueError::new_err(format!(
+                                    "Expected a single shape type, got '{name}'"
+                                )))
+                            }
+                        }
+                    }
+                    "color" => {
+                        let (r, g, b) = value.extract()?;
+                        combination = combination.color(r, g, b);
+                    }
+                    "size" => {
+                        let (low, high): (f64, f64) = value.extract()?;
+                        if low >= high {
+                            return Err(PyValueError::new_err(format!(
+                                "Empty size bucket [{low}, {high})"
+                            )));
+                        }
+                        combination = combination.size_bucket(low, high);
+                    }
+                    key => {
+                        return Err(PyValueError::new_err(format!(
+                            "Unknown combination attribute '{key}'"
+                        )))
+                    }
+                }
+            }
+            Ok(combination)
+        })
+        .collect()
+}
+
+/// Parses fill style names from Python: "filled", "outline" or
+/// "filled_outline", all sharing one stroke width and stroke color.
+fn parse_fill_styles(
+    names: Vec<String>,
+    stroke_width: f64,
+    stroke_color: (u8, u8, u8),
+) -> PyResult<Vec<FillStyle>> {
+    let (r, g, b) = stroke_color;
+    names
+        .iter()
+        .map(|name| match name.as_str() {
+            "filled" => Ok(FillStyle::Filled),
+            "outline" => Ok(FillStyle::Outline(stroke_width)),
+            "filled_outline" => Ok(FillStyle::FilledOutline(stroke_width, Color::new(r, g, b))),
+            _ => Err(PyValueError::new_err(format!(
+                "Unknown fill style '{name}'"
+            ))),
+        })
+        .collect()
+}
+
+/// Parses texture names from Python: "solid", "stripes", "checker", "dots" or
+/// "gradient". Stripes and gradients take an optional angle in degrees after a
+/// colon, e.g. "stripes:45".
+fn parse_textures(names: Vec<String>, frequency: f64) -> PyResult<Vec<Texture>> {
+    names
+        .iter()
+        .map(|name| {
+            let (kind, angle) = match name.split_once(':') {
+                Some((kind, degrees)) => {
+                    let degrees: f64 = degrees.parse().map_err(|_| {
+                        PyValueError::new_err(format!("Invalid texture angle in '{name}'"))
+                    })?;
+                    (kind, degrees.to_radians())
+                }
+                None => (name.as_str(), 0.),
+            };
+
+            match kind {
+                "solid" => Ok(Texture::Solid),
+                "stripes" => Ok(Texture::Stripes { angle, frequency }),
+                "checker" => Ok(Texture::Checker { frequency }),
+                "dots" => Ok(Texture::Dots {
+                    frequency,
+                    radius: 0.3,
+                }),
+                "gradient" => Ok(Texture::Gradient { angle }),
+                _ => Err(PyValueError::new_err(format!("Unknown texture '{name}'"))),
+            }
+        })
+        .collect()
+}
+
+/// Parses a distribution from a dict, or its JSON, as in `Distribution`'s
+/// serde representation.
+fn parse_distribution(py: Python<'_>, spec: &PyAny) -> PyResult<Distribution> {
+    let json: String = match spec.extract() {
+        Ok(json) => json,
+        Err(_) => py
+            .import("json")?
+            .call_method1("dumps", (spec,))?
+            .extract()?,
+    };
+    let distribution: Distribution = serde_json::from_str(&json)
+        .map_err(|e| PyValueError::new_err(format!("Invalid distribution: {e}")))?;
+    distribution.validate().map_err(PyValueError::new_err)?;
+    Ok(distribution)
+}
+
+/// Polar velocities from a speed distribution spec and an optional direction
+/// one, heading anywhere without it.
+fn parse_polar_velocity(
+    py: Python<'_>,
+    speed: &PyAny,
+    direction: Option<&PyAny>,
+) -> PyResult<VelocityDistribution> {
+    let speed = parse_distribution(py, speed)?;
+    Ok(match direction {
+        Some(direction) => VelocityDistribution::Polar {
+            speed,
+            direction: parse_distribution(py, direction)?,
+        },
+        None => VelocityDistribution::isotropic(speed),
+    })
+}
+
+/// `value` as Python objects following its serde representation, e.g. a
+/// string for unit variants and a dict for the others.
+fn to_py<T: Serialize>(py: Python<'_>, value: &T) -> PyResult<PyObject> {
+    fn convert(py: Python<'_>, value: serde_json::Value) -> PyResult<PyObject> {
+        use serde_json::Value;
+
+        Ok(match value {
+            Value::Null => py.None(),
+            Value::Bool(b) => b.to_object(py),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => i.to_object(py),
+                None => n.as_f64().to_object(py),
+            },
+            Value::String(s) => s.to_object(py),
+            Value::Array(items) => {
+                let items = items
+                    .into_iter()
+                    .map(|item| convert(py, item))
+                    .collect::<PyResult<Vec<_>>>()?;
+                PyList::new(py, items).to_object(py)
+            }
+            Value::Object(fields) => {
+                let dict = PyDict::new(py);
+                for (key, item) in fields {
+                    dict.set_item(key, convert(py, item)?)?;
+                }
+                dict.to_object(py)
+            }
+        })
+    }
+
+    convert(
+        py,
+        serde_json::to_value(value).expect("Failed to serialize value!"),
+    )
+}
+
+#[pyclass(name = "FunnyShapesDataset")]
+struct PyDataset {
+    inner: Dataset,
+    captioner: Captioner,
+    questions: QuestionGenerator,
+}
+
+/// Videos with their forward and, optionally, backward flows.
+type FlowBatch<'py> = (
+    &'py PyArray5<f64>,
+    &'py PyArray5<f64>,
+    Option<&'py PyArray5<f64>>,
+);
+
+impl PyDataset {
+    /// Applies builder methods to the wrapped dataset in place.
+    fn update(&mut self, f: impl FnOnce(Dataset) -> Dataset) {
+        self.inner = f(std::mem::take(&mut self.inner));
+    }
+
+    /// A random entry, raising `ValueError` if its shapes can't be placed or
+    /// the split allows none.
+    fn random_entry(&self) -> PyResult<Entry> {
+        self.inner
+            .try_generate_random_entry()
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    fn get_random_frame(&self, size: u16) -> PyResult<Array3<f64>> {
+        Ok(self.random_entry()?.render_entry(size))
+    }
+
+    /// The question generator, naming things as the captions do.
+    fn question_generator(&self) -> QuestionGenerator {
+        self.questions.clone().names(self.captioner.clone())
+    }
+
+    fn get_random_video(
+        &self,
+        num_frames: usize,
+        size: u16,
+        step_size: f64,
+    ) -> PyResult<Array4<f64>> {
+        Ok(self
+            .random_entry()?
+            .render_video(num_frames, size, step_size))
+    }
+}
+
+#[pymethods]
+impl PyDataset {
+    #[new]
+    #[pyo3(signature = (
+        colors,
+        size_range,
+        position_range,
+        num_shapes_range,
+        shape_types = None,
+        sprites = Vec::new(),
+        tint_sprites = false,
+        fill_styles = None,
+        stroke_width = 0.01,
+        stroke_color = (255, 255, 255),
+        textures = None,
+        texture_frequency = 4.0,
+        anchor = "top_left",
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        colors: Vec<(u8, u8, u8)>,
+        size_range: (f64, f64),
+        position_range: (f64, f64),
+        num_shapes_range: (usize, usize),
+        shape_types: Option<Vec<String>>,
+        sprites: Vec<String>,
+        tint_sprites: bool,
+        fill_styles: Option<Vec<String>>,
+        stroke_width: f64,
+        stroke_color: (u8, u8, u8),
+        textures: Option<Vec<String>>,
+        texture_frequency: f64,
+        anchor: &str,
+    ) -> PyResult<Self> {
+        let (size_lower, size_upper) = size_range;
+        let (position_lower, position_upper) = position_range;
+        let (shapes_lower, shapes_upper) = num_shapes_range;
+        let shape_types = match shape_types {
+            Some(names) => parse_shape_types(names, sprites.len())?,
+            None => vec![ShapeType::Square, ShapeType::Circle],
+        };
+        if shape_types.is_empty() {
+            return Err(PyValueError::new_err("Shape types must not be empty"));
+        }
+        let fill_styles = match fill_styles {
+            Some(names) => parse_fill_styles(names, stroke_width, stroke_color)?,
+            None => vec![FillStyle::Filled],
+        };
+        let textures = match textures {
+            Some(names) => parse_textures(names, texture_frequency)?,
+            None => vec![Texture::Solid],
+        };
+        let anchor = match anchor {
+            "top_left" => Anchor::TopLeft,
+            "center" => Anchor::Center,
+            _ => return Err(PyValueError::new_err(format!("Unknown anchor '{anchor}'"))),
+        };
+        // sprites go first, so the shape types referring to them are valid
+        let mut dataset = Dataset::new();
+        for path in sprites {
+            let sprite = Sprite::open(&path)
+                .map_err(|e| PyValueError::new_err(format!("Failed to load sprite {path}: {e}")))?;
+            dataset = dataset.add_sprite(sprite.tint(tint_sprites));
+        }
+
+        dataset = dataset
+            .shape_types(shape_types)
+            .fill_styles(fill_styles)
+            .textures(textures)
+            .size_range(size_lower, size_upper)
+            .position_range(position_lower, position_upper)
+            .anchor(anchor)
+            .velocity_range(-0.2, 0.2)
+            .num_shapes_range(shapes_lower, shapes_upper);
+
+        for (r, g, b) in colors {
+            dataset = dataset.add_color(r, g, b);
+        }
+
+        Ok(PyDataset {
+            inner: dataset,
+            captioner: Captioner::new(),
+            questions: QuestionGenerator::new(),
+        })
+    }
+
+    #[pyo3(signature = (gravity = (0.0, 0.0), drag = 0.0, restitution = 1.0, mass_range = (1.0, 1.0)))]
+    fn set_physics(
+        &mut self,
+        gravity: (f64, f64),
+        drag: f64,
+        restitution: f64,
+        mass_range: (f64, f64),
+    ) -> PyResult<()> {
+        if !(0.0..=1.0).contains(&restitution) {
+            return Err(PyValueError::new_err("restitution must be in range [0, 1]"));
+        }
+        if mass_range.0 <= 0.0 || mass_range.1 < mass_range.0 {
+            return Err(PyValueError::new_err(
+                "mass_range must be positive and ordered",
+            ));
+        }
+
+        let (gx, gy) = gravity;
+        let (mass_lower, mass_upper) = mass_range;
+        self.update(|dataset| {
+            dataset
+                .gravity(gx, gy)
+                .drag(drag)
+                .restitution(restitution)
+                .mass_range(mass_lower, mass_upper)
+        });
+        Ok(())
+    }
+
+    /// Sets what shapes do at the image edges: "reflect", "wrap", "open" or
+    /// "absorb".
+    fn set_boundary(&mut self, boundary: &str) -> PyResult<()> {
+        let boundary = match boundary {
+            "reflect" => Boundary::Reflect,
+            "wrap" => Boundary::Wrap,
+            "open" => Boundary::Open,
+            "absorb" => Boundary::Absorb,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown boundary '{boundary}'"
+                )))
+            }
+        };
+
+        self.update(|dataset| dataset.boundary(boundary));
+        Ok(())
+    }
+
+    /// Sets the distribution of "size", "position", "velocity", "mass" or
+    /// "num_shapes" from a dict (or its JSON) naming its `type`, e.g.
+    /// `{"type": "normal", "mean": 0.1, "std": 0.02}`. Types are "constant",
+    /// "uniform", "normal", "truncated_normal", "log_uniform", "choice" and
+    /// "mixture".
+    fn set_distribution(&mut self, py: Python<'_>, parameter: &str, spec: &PyAny) -> PyResult<()> {
+        let distribution = parse_distribution(py, spec)?;
+
+        match parameter {
+            "size" => self.update(|dataset| dataset.size_distribution(distribution)),
+            "position" => self.update(|dataset| dataset.position_distribution(distribution)),
+            "velocity" => self.update(|dataset| dataset.velocity_distribution(distribution)),
+            "mass" => self.update(|dataset| dataset.mass_distribution(distribution)),
+            "num_shapes" => self.update(|dataset| dataset.num_shapes_distribution(distribution)),
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown parameter '{parameter}'"
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws velocities as a `speed` and a `direction` in radians, clockwise
+    /// from the x axis, each a distribution as in `set_distribution`. Without
+    /// a direction, shapes head anywhere. Setting the "velocity" distribution
+    /// goes back to drawing each component.
+    #[pyo3(signature = (speed, direction = None))]
+    fn set_polar_velocity(
+        &mut self,
+        py: Python<'_>,
+        speed: &PyAny,
+        direction: Option<&PyAny>,
+    ) -> PyResult<()> {
+        if let VelocityDistribution::Polar { speed, direction } =
+            parse_polar_velocity(py, speed, direction)?
+        {
+            self.update(|dataset| dataset.polar_velocity(speed, direction));
+        }
+        Ok(())
+    }
+
+    /// Overrides the colors and the "size", "velocity" or "mass"
+    /// distributions (as in `set_distribution`) for shapes of the given types,
+    /// named as in the constructor. Velocities can instead be overridden with
+    /// a `speed` and `direction`, as in `set_polar_velocity`.
+    #[pyo3(signature = (
+        shape_types,
+        colors = None,
+        size = None,
+        velocity = None,
+        mass = None,
+        speed = None,
+        direction = None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn set_shape_overrides(
+        &mut self,
+        py: Python<'_>,
+        shape_types: Vec<String>,
+        colors: Option<Vec<(u8, u8, u8)>>,
+        size: Option<&PyAny>,
+        velocity: Option<&PyAny>,
+        mass: Option<&PyAny>,
+        speed: Option<&PyAny>,
+        direction: Option<&PyAny>,
+    ) -> PyResult<()> {
+        let shape_types = parse_shape_types(shape_types, self.inner.get_sprites().len())?;
+        if colors.as_ref().is_some_and(|colors| colors.is_empty()) {
+            return Err(PyValueError::new_err("Overridden colors must not be empty"));
+        }
+
+        let mut overrides = ShapeOverrides::new();
+        for (r, g, b) in colors.into_iter().flatten() {
+            overrides = overrides.add_color(r, g, b);
+        }
+        if let Some(size) = size {
+            overrides = overrides.size(parse_distribution(py, size)?);
+        }
+        if let Some(velocity) = velocity {
+            overrides = overrides.velocity(parse_distribution(py, velocity)?);
+        }
+        if let Some(mass) = mass {
+            overrides = overrides.mass(parse_distribution(py, mass)?);
+        }
+        match (velocity.is_some(), speed) {
+            (true, Some(_)) => {
+                return Err(PyValueError::new_err(
+                    "Pass either a velocity or a speed, not both",
+                ))
+            }
+            (false, Some(speed)) => {
+                overrides.velocity = Some(parse_polar_velocity(py, speed, direction)?);
+            }
+            (_, None) if direction.is_some() => {
+                return Err(PyValueError::new_err("A direction needs a speed"))
+            }
+            _ => {}
+        }
+
+        for shape_type in shape_types {
+            let overrides = overrides.clone();
+            self.update(|dataset| dataset.override_shape_type(shape_type, overrides));
+        }
+        Ok(())
     }
 
-    impl PyDataset {
-        fn get_random_frame(&self, size: u16) -> Array3<f64> {
-            self.inner.get_random_image_array(size)
-            // let entry = self.inner.generate_random_entry();
-            // entry.render_entry(size)
+    /// Restricts shapes to the "train", "val" or "test" part of a
+    /// compositional hold-out split. `val` and `test` list the combinations
+    /// held out for each, as dicts with any of a "shape_type", an (r, g, b)
+    /// "color" and a (low, high) "size" bucket, e.g.
+    /// `[{"shape_type": "circle", "color": (255, 0, 0)}]`. Training shapes
+    /// match none of them, and validation and test shapes match one of
+    /// their own.
+    #[pyo3(signature = (mode, val = Vec::new(), test = Vec::new()))]
+    fn set_split(&mut self, mode: &str, val: Vec<&PyDict>, test: Vec<&PyDict>) -> PyResult<()> {
+        let mode = match mode {
+            "train" => SplitMode::Train,
+            "val" => SplitMode::Val,
+            "test" => SplitMode::Test,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown split mode '{mode}'"
+                )))
+            }
+        };
+
+        let num_sprites = self.inner.get_sprites().len();
+        let mut split = Split::new().mode(mode);
+        for combination in parse_combinations(val, num_sprites)? {
+            split = split.add_val(combination);
+        }
+        for combination in parse_combinations(test, num_sprites)? {
+            split = split.add_test(combination);
         }
 
-        fn get_random_video(&self, num_frames: usize, size: u16, step_size: f64) -> Array4<f64> {
-            self.inner
-                .get_random_video_array(num_frames, size, step_size)
+        self.update(|dataset| dataset.split(split));
+        Ok(())
+    }
+
+    /// Sets how shapes are placed: "independent", "no_overlap",
+    /// "min_separation" (boxes at least `value` apart), "max_occlusion" (at
+    /// most a `value` fraction of each box covered) or "min_visibility" (at
+    /// least a `value` fraction of each shape visible).
+    #[pyo3(signature = (placement, value = 0.0, max_tries = 1000))]
+    fn set_placement(&mut self, placement: &str, value: f64, max_tries: usize) -> PyResult<()> {
+        let placement = match placement {
+            "independent" => Placement::Independent,
+            "no_overlap" => Placement::NoOverlap,
+            "min_separation" => Placement::MinSeparation(value),
+            "max_occlusion" => Placement::MaxOcclusion(value),
+            "min_visibility" => Placement::MinVisibility(value),
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown placement '{placement}'"
+                )))
+            }
+        };
+
+        self.update(|dataset| dataset.placement(placement).max_placement_tries(max_tries));
+        Ok(())
+    }
+
+    /// Adds obstacles to every entry: explicit `rectangles` as (x, y, width,
+    /// height) and `segments` as (x1, y1, x2, y2, thickness), plus a random
+    /// number of random "rectangle" and/or "segment" obstacles.
+    #[pyo3(signature = (
+        rectangles = Vec::new(),
+        segments = Vec::new(),
+        num_obstacles_range = (0, 0),
+        obstacle_types = vec!["rectangle".to_string()],
+        size_range = (0.05, 0.3),
+        color = (128, 128, 128),
+    ))]
+    fn set_obstacles(
+        &mut self,
+        rectangles: Vec<(f64, f64, f64, f64)>,
+        segments: Vec<(f64, f64, f64, f64, f64)>,
+        num_obstacles_range: (usize, usize),
+        obstacle_types: Vec<String>,
+        size_range: (f64, f64),
+        color: (u8, u8, u8),
+    ) -> PyResult<()> {
+        let obstacle_types = obstacle_types
+            .iter()
+            .map(|name| match name.as_str() {
+                "rectangle" => Ok(ObstacleType::Rectangle),
+                "segment" => Ok(ObstacleType::Segment),
+                _ => Err(PyValueError::new_err(format!(
+                    "Unknown obstacle type '{name}'"
+                ))),
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        if rectangles
+            .iter()
+            .any(|&(_, _, width, height)| width <= 0.0 || height <= 0.0)
+        {
+            return Err(PyValueError::new_err(
+                "Rectangle width and height must be positive",
+            ));
         }
+        if segments.iter().any(|&(.., thickness)| thickness <= 0.0) {
+            return Err(PyValueError::new_err("Segment thickness must be positive"));
+        }
+        if size_range.0 <= 0.0 || size_range.1 < size_range.0 || size_range.1 > 1.0 {
+            return Err(PyValueError::new_err(
+                "size_range must be ordered and in (0, 1]",
+            ));
+        }
+
+        let (r, g, b) = color;
+        let (obstacles_lower, obstacles_upper) = num_obstacles_range;
+        let (size_lower, size_upper) = size_range;
+        self.update(|mut dataset| {
+            for (x, y, width, height) in rectangles {
+                dataset =
+                    dataset.add_obstacle(Obstacle::rectangle(x, y, width, height).color(r, g, b));
+            }
+            for (x1, y1, x2, y2, thickness) in segments {
+                dataset = dataset
+                    .add_obstacle(Obstacle::segment(x1, y1, x2, y2, thickness).color(r, g, b));
+            }
+
+            dataset
+                .obstacle_types(obstacle_types)
+                .obstacle_size_range(size_lower, size_upper)
+                .obstacle_color(r, g, b)
+                .num_obstacles_range(obstacles_lower, obstacles_upper)
+        });
+        Ok(())
+    }
+
+    #[pyo3(name = "get_random_frame")]
+    fn get_random_frame_py<'py>(&self, py: Python<'py>, size: u16) -> PyResult<&'py PyArray3<f64>> {
+        let array = self.get_random_frame(size)?;
+        Ok(array.into_pyarray(py))
+    }
+
+    #[pyo3(name = "get_random_frame_batch")]
+    fn get_random_frame_batch_py<'py>(
+        &self,
+        py: Python<'py>,
+        batch_size: usize,
+        size: u16,
+    ) -> PyResult<&'py PyArray4<f64>> {
+        let frames = (0..batch_size)
+            .into_par_iter()
+            .map(|_| self.get_random_frame(size))
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let frames: Vec<ArrayView3<f64>> = frames.iter().map(ArrayView3::from).collect();
+        let frames = stack(Axis(0), &frames).expect("Failed to stack frames!");
+
+        Ok(frames.to_pyarray(py))
     }
 
-    #[pymethods]
-    impl PyDataset {
-        #[new]
-        fn new(
-            colors: Vec<(u8, u8, u8)>,
-            size_range: (f64, f64),
-            position_range: (f64, f64),
-            num_shapes_range: (usize, usize),
-        ) -> Self {
-            let (size_lower, size_upper) = size_range;
-            let (position_lower, position_upper) = position_range;
-            let (shapes_lower, shapes_upper) = num_shapes_range;
-            let mut dataset = Dataset::new()
-                .shape_types(vec![ShapeType::Square, ShapeType::Circle])
-                .size_range(size_lower, size_upper)
-                .position_range(position_lower, position_upper)
-                .velocity_range(-0.2, 0.2)
-                .num_shapes_range(shapes_lower, shapes_upper);
+    /// Configures captions: "brief" lists shape types, "standard" adds
+    /// colors and "detailed" adds spatial relations. Each caption fills one
+    /// of `templates` at random, with `{count}`, `{objects}` and
+    /// `{relations}` placeholders. `color_names` maps (r, g, b) colors to
+    /// names, which otherwise come from the closest basic color, and
+    /// `shape_names` maps shape types to (singular, plural) nouns.
+    #[pyo3(signature = (
+        verbosity = "standard",
+        templates = Vec::new(),
+        color_names = Vec::new(),
+        shape_names = Vec::new()
+    ))]
+    fn set_captions(
+        &mut self,
+        verbosity: &str,
+        templates: Vec<String>,
+        color_names: Vec<((u8, u8, u8), String)>,
+        shape_names: Vec<(String, (String, String))>,
+    ) -> PyResult<()> {
+        let verbosity = match verbosity {
+            "brief" => Verbosity::Brief,
+            "standard" => Verbosity::Standard,
+            "detailed" => Verbosity::Detailed,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown verbosity '{verbosity}'"
+                )))
+            }
+        };
 
-            for (r, g, b) in colors {
-                dataset = dataset.add_color(r, g, b);
+        let mut captioner = Captioner::new().verbosity(verbosity);
+        for template in templates {
+            captioner = captioner.add_template(&template);
+        }
+        for ((r, g, b), name) in color_names {
+            captioner = captioner.color_name(r, g, b, &name);
+        }
+        let num_sprites = self.inner.get_sprites().len();
+        for (name, (singular, plural)) in shape_names {
+            for shape_type in parse_shape_types(vec![name], num_sprites)? {
+                captioner = captioner.shape_name(shape_type, &singular, &plural);
             }
+        }
+
+        self.captioner = captioner;
+        Ok(())
+    }
+
+    /// Returns `(frame, caption)`, with captions as set by `set_captions`.
+    #[pyo3(name = "get_random_frame_with_caption")]
+    fn get_random_frame_with_caption_py<'py>(
+        &self,
+        py: Python<'py>,
+        size: u16,
+    ) -> PyResult<(&'py PyArray3<f64>, String)> {
+        let entry = self.random_entry()?;
+        let caption = self.captioner.caption(&entry);
+        Ok((entry.render_entry(size).into_pyarray(py), caption))
+    }
+
+    /// Returns `(frames, captions)`, with a caption per frame.
+    #[pyo3(name = "get_random_frame_batch_with_captions")]
+    fn get_random_frame_batch_with_captions_py<'py>(
+        &self,
+        py: Python<'py>,
+        batch_size: usize,
+        size: u16,
+    ) -> PyResult<(&'py PyArray4<f64>, Vec<String>)> {
+        let samples = (0..batch_size)
+            .into_par_iter()
+            .map(|_| {
+                let entry = self.random_entry()?;
+                Ok((entry.render_entry(size), self.captioner.caption(&entry)))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let (frames, captions): (Vec<_>, Vec<_>) = samples.into_iter().unzip();
+        let frames: Vec<ArrayView3<f64>> = frames.iter().map(ArrayView3::from).collect();
+        let frames = stack(Axis(0), &frames).expect("Failed to stack frames!");
+
+        Ok((frames.to_pyarray(py), captions))
+    }
 
-            PyDataset { inner: dataset }
+    /// Configures questions: `question_types` from "count", "query_color",
+    /// "query_shape", "closest", "furthest" and "left_of", all by default.
+    /// Counts above `max_count` are never asked about, and `balanced`
+    /// samples question types and then answers uniformly. Colors and shapes
+    /// are named as in captions.
+    #[pyo3(signature = (question_types = None, max_count = 10, balanced = true))]
+    fn set_questions(
+        &mut self,
+        question_types: Option<Vec<String>>,
+        max_count: usize,
+        balanced: bool,
+    ) -> PyResult<()> {
+        let question_types = match question_types {
+            Some(names) => names
+                .iter()
+                .map(|name| name.parse().map_err(PyValueError::new_err))
+                .collect::<PyResult<Vec<_>>>()?,
+            None => QuestionType::all(),
+        };
+        if question_types.is_empty() {
+            return Err(PyValueError::new_err("Question types must not be empty"));
         }
 
-        #[pyo3(name = "get_random_frame")]
-        fn get_random_frame_py<'py>(&self, py: Python<'py>, size: u16) -> &'py PyArray3<f64> {
-            let array = self.get_random_frame(size);
-            array.into_pyarray(py)
+        self.questions = QuestionGenerator::new()
+            .question_types(question_types)
+            .max_count(max_count)
+            .balanced(balanced);
+        Ok(())
+    }
+
+    /// Every answer questions can have, e.g. to index answer classes.
+    fn question_vocabulary(&self) -> Vec<String> {
+        self.question_generator().vocabulary(&self.inner)
+    }
+
+    /// Returns `(frame, questions)`, where `questions` holds up to
+    /// `num_questions` dicts with a `type`, `question` and `answer`.
+    #[pyo3(name = "get_random_frame_with_questions")]
+    fn get_random_frame_with_questions_py<'py>(
+        &self,
+        py: Python<'py>,
+        size: u16,
+        num_questions: usize,
+    ) -> PyResult<(&'py PyArray3<f64>, Vec<&'py PyDict>)> {
+        let entry = self.random_entry()?;
+        let questions = self
+            .question_generator()
+            .generate(&self.inner, &entry, num_questions);
+        let frame = entry.render_entry(size);
+
+        let mut dicts = Vec::with_capacity(questions.len());
+        for question in questions {
+            let dict = PyDict::new(py);
+            dict.set_item("type", question.question_type.as_str())?;
+            dict.set_item("question", question.question)?;
+            dict.set_item("answer", question.answer)?;
+            dicts.push(dict);
         }
 
-        #[pyo3(name = "get_random_frame_batch")]
-        fn get_random_frame_batch_py<'py>(
-            &self,
-            py: Python<'py>,
-            batch_size: usize,
-            size: u16,
-        ) -> &'py PyArray4<f64> {
-            let mut frames = Vec::with_capacity(batch_size);
-            (0..batch_size)
-                .into_par_iter()
-                .map(|_| self.get_random_frame(size))
-                .collect_into_vec(&mut frames);
+        Ok((frame.into_pyarray(py), dicts))
+    }
 
-            let frames: Vec<ArrayView3<f64>> = frames.iter().map(|f| ArrayView3::from(f)).collect();
-            let frames = stack(Axis(0), &frames).expect("TODO");
+    /// Returns `(frame, scene_graph)`, where `scene_graph` is a dict with
+    /// `nodes` for the shapes and their attributes, with colors named as in
+    /// captions, and `edges` with a `source`, `target` and `relation` out of
+    /// "left_of", "above", "overlaps", "touching" and "contains", computed
+    /// from the shapes' silhouettes.
+    #[pyo3(name = "get_random_frame_with_scene_graph")]
+    #[pyo3(signature = (size, touch_distance = 0.01))]
+    fn get_random_frame_with_scene_graph_py<'py>(
+        &self,
+        py: Python<'py>,
+        size: u16,
+        touch_distance: f64,
+    ) -> PyResult<(&'py PyArray3<f64>, &'py PyDict)> {
+        let entry = self.random_entry()?;
+        let frame = entry.render_entry(size);
+        let graph = SceneGraph::new(&entry, touch_distance, &self.captioner);
 
-            frames.to_pyarray(py)
+        let mut nodes = Vec::with_capacity(graph.nodes.len());
+        for node in graph.nodes {
+            let dict = PyDict::new(py);
+            dict.set_item("id", node.id)?;
+            dict.set_item("shape_type", node.shape_type.to_string())?;
+            dict.set_item("color", (node.color.0, node.color.1, node.color.2))?;
+            dict.set_item("color_name", node.color_name)?;
+            dict.set_item("fill_style", to_py(py, &node.fill_style)?)?;
+            dict.set_item("texture", to_py(py, &node.texture)?)?;
+            dict.set_item("size", node.size)?;
+            dict.set_item("center", node.center)?;
+            dict.set_item("bbox", node.bbox)?;
+            dict.set_item("velocity", node.velocity)?;
+            dict.set_item("mass", node.mass)?;
+            nodes.push(dict);
+        }
+        let mut edges = Vec::with_capacity(graph.edges.len());
+        for edge in graph.edges {
+            let dict = PyDict::new(py);
+            dict.set_item("source", edge.source)?;
+            dict.set_item("target", edge.target)?;
+            dict.set_item("relation", to_py(py, &edge.relation)?)?;
+            edges.push(dict);
         }
 
-        #[pyo3(name = "get_random_video")]
-        fn get_random_video_py<'py>(
-            &self,
-            py: Python<'py>,
-            num_frames: usize,
-            size: u16,
-            step_size: f64,
-        ) -> &'py PyArray4<f64> {
-            let array = self.get_random_video(num_frames, size, step_size);
-            array.into_pyarray(py)
+        let graph = PyDict::new(py);
+        graph.set_item("nodes", nodes)?;
+        graph.set_item("edges", edges)?;
+        Ok((frame.into_pyarray(py), graph))
+    }
+
+    #[pyo3(name = "get_random_video")]
+    fn get_random_video_py<'py>(
+        &self,
+        py: Python<'py>,
+        num_frames: usize,
+        size: u16,
+        step_size: f64,
+    ) -> PyResult<&'py PyArray4<f64>> {
+        let array = self.get_random_video(num_frames, size, step_size)?;
+        Ok(array.into_pyarray(py))
+    }
+
+    /// Returns `(video, tracks)`, where `tracks` holds a dict per shape per
+    /// frame with its `frame`, `id`, `position`, `velocity`, pixel `bbox` as
+    /// (left, top, width, height) and `visibility`.
+    #[pyo3(name = "get_random_video_tracks")]
+    fn get_random_video_tracks_py<'py>(
+        &self,
+        py: Python<'py>,
+        num_frames: usize,
+        size: u16,
+        step_size: f64,
+    ) -> PyResult<(&'py PyArray4<f64>, Vec<&'py PyDict>)> {
+        let (video, annotations) = self
+            .random_entry()?
+            .render_video_with_tracks(num_frames, size, step_size);
+
+        let mut tracks = Vec::with_capacity(annotations.len());
+        for annotation in annotations {
+            let track = PyDict::new(py);
+            track.set_item("frame", annotation.frame)?;
+            track.set_item("id", annotation.id)?;
+            track.set_item("position", annotation.position)?;
+            track.set_item("velocity", annotation.velocity)?;
+            track.set_item("bbox", annotation.bbox)?;
+            track.set_item("visibility", annotation.visibility)?;
+            tracks.push(track);
         }
 
-        #[pyo3(name = "get_random_video_batch")]
-        fn get_random_video_batch_py<'py>(
-            &self,
-            py: Python<'py>,
-            batch_size: usize,
-            num_frames: usize,
-            size: u16,
-            step_size: f64,
-        ) -> &'py PyArray5<f64> {
-            let mut batch = Vec::with_capacity(batch_size);
-            (0..batch_size)
-                .into_par_iter()
-                .map(|_| self.get_random_video(num_frames, size, step_size))
-                .collect_into_vec(&mut batch);
+        Ok((video.into_pyarray(py), tracks))
+    }
+
+    /// Saves a random video as a GIF, APNG, Y4M or raw RGB stream, depending
+    /// on the extension of `path`. With `tracks_path`, also saves its tracks
+    /// as MOTChallenge text (".txt") or JSON (".json").
+    #[pyo3(signature = (path, num_frames, size, step_size, frame_delay_ms = 50, tracks_path = None))]
+    fn save_random_video(
+        &self,
+        path: &str,
+        num_frames: usize,
+        size: u16,
+        step_size: f64,
+        frame_delay_ms: u16,
+        tracks_path: Option<&str>,
+    ) -> PyResult<()> {
+        let video = match tracks_path {
+            Some(tracks_path) => {
+                let (video, annotations) = self
+                    .random_entry()?
+                    .render_video_with_tracks(num_frames, size, step_size);
+                crate::save_tracks(tracks_path, &annotations)?;
+                video
+            }
+            None => self.get_random_video(num_frames, size, step_size)?,
+        };
+        crate::save_video(path, &video, frame_delay_ms)?;
+        Ok(())
+    }
+
+    #[pyo3(name = "get_random_video_batch")]
+    fn get_random_video_batch_py<'py>(
+        &self,
+        py: Python<'py>,
+        batch_size: usize,
+        num_frames: usize,
+        size: u16,
+        step_size: f64,
+    ) -> PyResult<&'py PyArray5<f64>> {
+        let batch = (0..batch_size)
+            .into_par_iter()
+            .map(|_| self.get_random_video(num_frames, size, step_size))
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let batch: Vec<ArrayView4<f64>> = batch.iter().map(ArrayView4::from).collect();
+        let batch = stack(Axis(0), &batch).expect("Failed to stack videos!");
+
+        Ok(batch.to_pyarray(py))
+    }
+
+    /// Returns `(videos, forward_flow, backward_flow)`, where `backward_flow`
+    /// is `None` unless `backward` is set. Flows have one fewer frame than the
+    /// videos and two (dx, dy) channels in pixels.
+    #[pyo3(name = "get_random_video_flow_batch")]
+    #[pyo3(signature = (batch_size, num_frames, size, step_size, backward = false))]
+    fn get_random_video_flow_batch_py<'py>(
+        &self,
+        py: Python<'py>,
+        batch_size: usize,
+        num_frames: usize,
+        size: u16,
+        step_size: f64,
+        backward: bool,
+    ) -> PyResult<FlowBatch<'py>> {
+        let batch = (0..batch_size)
+            .into_par_iter()
+            .map(|_| {
+                Ok(self
+                    .random_entry()?
+                    .render_video_with_flow(num_frames, size, step_size))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let stack_batch = |arrays: Vec<ArrayView4<f64>>| {
+            stack(Axis(0), &arrays)
+                .expect("Failed to stack flows!")
+                .to_pyarray(py)
+        };
+        let videos = stack_batch(batch.iter().map(|(v, _, _)| v.view()).collect());
+        let forward = stack_batch(batch.iter().map(|(_, f, _)| f.view()).collect());
+
+        let backward =
+            backward.then(|| stack_batch(batch.iter().map(|(_, _, b)| b.view()).collect()));
+        Ok((videos, forward, backward))
+    }
+}
+
+/// Gym-style environment: `reset` returns an observation and `step` returns
+/// `(observation, reward, done, info)`.
+#[pyclass(name = "FunnyShapesEnv")]
+struct PyEnvironment {
+    inner: Environment,
+    size: u16,
+    continuous: bool,
+}
 
-            let batch: Vec<ArrayView4<f64>> = batch.iter().map(|f| ArrayView4::from(f)).collect();
-            let batch = stack(Axis(0), &batch).expect("TODO");
+#[pymethods]
+impl PyEnvironment {
+    #[new]
+    #[pyo3(signature = (
+        task = "reach_target",
+        size = 64,
+        continuous = false,
+        num_obstacles = 3,
+        max_steps = 200,
+        step_size = 0.05,
+    ))]
+    fn new(
+        task: &str,
+        size: u16,
+        continuous: bool,
+        num_obstacles: usize,
+        max_steps: usize,
+        step_size: f64,
+    ) -> PyResult<Self> {
+        let task = match task {
+            "reach_target" => Task::ReachTarget,
+            "avoid_shapes" => Task::AvoidShapes,
+            "avoid_obstacles" => Task::AvoidObstacles,
+            _ => return Err(PyValueError::new_err(format!("Unknown task '{task}'"))),
+        };
 
-            batch.to_pyarray(py)
+        let inner = Environment::new(task)
+            .image_size(size)
+            .num_obstacles(num_obstacles)
+            .max_steps(max_steps)
+            .step_size(step_size);
+        Ok(PyEnvironment {
+            inner,
+            size,
+            continuous,
+        })
+    }
+
+    /// Number of discrete actions, or the size of continuous actions.
+    #[getter]
+    fn num_actions(&self) -> usize {
+        if self.continuous {
+            2
+        } else {
+            NUM_DISCRETE_ACTIONS
         }
     }
 
-    m.add_class::<PyDataset>()?;
-    Ok(())
+    #[getter]
+    fn observation_shape(&self) -> (usize, usize, usize) {
+        (3, self.size as usize, self.size as usize)
+    }
+
+    #[pyo3(signature = (seed = None))]
+    fn reset<'py>(&mut self, py: Python<'py>, seed: Option<u64>) -> &'py PyArray3<f64> {
+        self.inner.reset(seed).into_pyarray(py)
+    }
+
+    fn step<'py>(
+        &mut self,
+        py: Python<'py>,
+        action: &PyAny,
+    ) -> PyResult<(&'py PyArray3<f64>, f64, bool, &'py PyDict)> {
+        let action = if self.continuous {
+            let (x, y): (f64, f64) = action.extract()?;
+            Action::Continuous(x, y)
+        } else {
+            let action: usize = action.extract()?;
+            if action >= NUM_DISCRETE_ACTIONS {
+                return Err(PyValueError::new_err(format!("Invalid action {action}")));
+            }
+            Action::Discrete(action)
+        };
+
+        if self.inner.entry().shapes().is_empty() {
+            return Err(PyValueError::new_err("reset must be called before step"));
+        }
+
+        let step = self.inner.step(action);
+        Ok((
+            step.observation.into_pyarray(py),
+            step.reward,
+            step.done,
+            PyDict::new(py),
+        ))
+    }
 }
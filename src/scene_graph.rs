@@ -0,0 +1,258 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use super::caption::Captioner;
+use super::entry::Entry;
+use super::placement::{box_distance, shape_box, shape_center, BoundingBox};
+use super::shape::{Color, FillStyle, ShapeType, Texture};
+
+/// Resolution shapes are rasterized at to find which overlap, touch or
+/// contain each other.
+const RELATION_SIZE: u16 = 128;
+
+/// Relations between pairs of shapes in a `SceneGraph`. Overlapping, touching
+/// and containing are computed from the shapes' silhouettes, rasterized at
+/// `RELATION_SIZE`, so circles only touch where their outlines do.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpatialRelation {
+    /// The source's center is left of the target's.
+    LeftOf,
+    /// The source's center is above the target's.
+    Above,
+    /// The shapes share pixels. Added once per pair, from the lower id.
+    Overlaps,
+    /// The shapes are within the touch distance without overlapping. Added
+    /// once per pair, from the lower id.
+    Touching,
+    /// Every pixel of the target is inside the source.
+    Contains,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SceneNode {
+    /// Index of the shape in the entry, which is also its drawing order.
+    pub id: usize,
+    pub shape_type: ShapeType,
+    pub color: Color,
+    /// The color's name, as in captions.
+    pub color_name: String,
+    pub fill_style: FillStyle,
+    pub texture: Texture,
+    pub size: f64,
+    pub center: (f64, f64),
+    /// As (x1, y1, x2, y2).
+    pub bbox: BoundingBox,
+    pub velocity: (f64, f64),
+    pub mass: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SceneEdge {
+    pub source: usize,
+    pub target: usize,
+    pub relation: SpatialRelation,
+}
+
+/// The shapes of an entry with their attributes and pairwise spatial
+/// relations, as it currently is.
+#[derive(Debug, Clone, Serialize)]
+pub struct SceneGraph {
+    pub nodes: Vec<SceneNode>,
+    pub edges: Vec<SceneEdge>,
+}
+
+/// The pixels inside a shape at `RELATION_SIZE`.
+struct Silhouette {
+    pixels: HashSet<(isize, isize)>,
+    /// Pixels next to one outside the shape, the only ones the closest pixels
+    /// of two shapes can be.
+    edge: Vec<(isize, isize)>,
+}
+
+impl Silhouette {
+    fn new(pixels: Vec<(isize, isize)>) -> Self {
+        let pixels: HashSet<_> = pixels.into_iter().collect();
+        let edge = pixels
+            .iter()
+            .copied()
+            .filter(|&(x, y)| {
+                [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                    .iter()
+                    .any(|p| !pixels.contains(p))
+            })
+            .collect();
+        Silhouette { pixels, edge }
+    }
+
+    fn overlaps(&self, other: &Silhouette) -> bool {
+        !self.pixels.is_disjoint(&other.pixels)
+    }
+
+    fn contains(&self, other: &Silhouette) -> bool {
+        !other.pixels.is_empty() && other.pixels.is_subset(&self.pixels)
+    }
+
+    /// Distance in image units between the closest pixels of two shapes that
+    /// do not overlap, less the pixel between neighbours.
+    fn gap(&self, other: &Silhouette) -> f64 {
+        let closest = self
+            .edge
+            .iter()
+            .flat_map(|&(ax, ay)| {
+                other
+                    .edge
+                    .iter()
+                    .map(move |&(bx, by)| ((ax - bx) as f64).hypot((ay - by) as f64))
+            })
+            .fold(f64::INFINITY, f64::min);
+        (closest - 1.).max(0.) / RELATION_SIZE as f64
+    }
+}
+
+impl SceneGraph {
+    /// Scene graph of `entry`, where shapes less than `touch_distance` apart
+    /// are touching and colors are named as `names` does.
+    pub fn new(entry: &Entry, touch_distance: f64, names: &Captioner) -> Self {
+        let nodes: Vec<SceneNode> = entry
+            .shapes()
+            .iter()
+            .enumerate()
+            .map(|(id, shape)| SceneNode {
+                id,
+                shape_type: shape.shape_type,
+                color: shape.color,
+                color_name: names.describe_color(shape.color),
+                fill_style: shape.fill_style,
+                texture: shape.texture,
+                size: shape.size.0,
+                center: shape_center(shape),
+                bbox: shape_box(shape),
+                velocity: (shape.velocity.0, shape.velocity.1),
+                mass: shape.mass.0,
+            })
+            .collect();
+
+        let silhouettes: Vec<Silhouette> = entry
+            .shape_pixels(RELATION_SIZE)
+            .into_iter()
+            .map(Silhouette::new)
+            .collect();
+
+        let mut edges = Vec::new();
+        let mut add = |source, target, relation| {
+            edges.push(SceneEdge {
+                source,
+                target,
+                relation,
+            })
+        };
+        for a in &nodes {
+            for b in nodes.iter().filter(|b| b.id != a.id) {
+                if a.center.0 < b.center.0 {
+                    add(a.id, b.id, SpatialRelation::LeftOf);
+                }
+                if a.center.1 < b.center.1 {
+                    add(a.id, b.id, SpatialRelation::Above);
+                }
+                let (sa, sb) = (&silhouettes[a.id], &silhouettes[b.id]);
+                if a.id < b.id {
+                    if sa.overlaps(sb) {
+                        add(a.id, b.id, SpatialRelation::Overlaps);
+                    } else if box_distance(a.bbox, b.bbox) < touch_distance
+                        && sa.gap(sb) < touch_distance
+                    {
+                        // shapes are never closer than their boxes
+                        add(a.id, b.id, SpatialRelation::Touching);
+                    }
+                }
+                if sa.contains(sb) {
+                    add(a.id, b.id, SpatialRelation::Contains);
+                }
+            }
+        }
+
+        SceneGraph { nodes, edges }
+    }
+
+    /// Edges from `source` with `relation`, as target ids.
+    pub fn related(&self, source: usize, relation: SpatialRelation) -> Vec<usize> {
+        self.edges
+            .iter()
+            .filter(|e| e.source == source && e.relation == relation)
+            .map(|e| e.target)
+            .collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize scene graph!")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Shape;
+
+    #[test]
+    fn relations_follow_geometry() {
+        let shapes = [
+            (0.1, 0.1, 0.4),
+            (0.2, 0.2, 0.1),
+            (0.5, 0.1, 0.1),
+            (0.6, 0.6, 0.1),
+        ]
+        .into_iter()
+        .map(|(x, y, size)| Shape::new(&ShapeType::Square).size(size).position(x, y))
+        .collect();
+        let names = Captioner::new().color_name(255, 255, 255, "snow");
+        let graph = SceneGraph::new(&Entry::new(shapes), 0.01, &names);
+
+        assert_eq!(graph.related(0, SpatialRelation::Contains), vec![1]);
+        assert_eq!(graph.related(0, SpatialRelation::Overlaps), vec![1]);
+        assert_eq!(graph.related(0, SpatialRelation::Touching), vec![2]);
+        assert_eq!(
+            graph.related(3, SpatialRelation::LeftOf),
+            Vec::<usize>::new()
+        );
+        assert_eq!(graph.related(2, SpatialRelation::Above), vec![0, 1, 3]);
+        assert!(graph.to_json().contains(r#""relation":"left_of""#));
+        assert!(graph.to_json().contains(r#""relation":"contains""#));
+        assert_eq!(graph.nodes[0].color_name, "snow");
+    }
+
+    #[test]
+    fn relations_follow_silhouettes() {
+        let circle = |x, y, size| Shape::new(&ShapeType::Circle).size(size).position(x, y);
+        let square = |x, y, size| Shape::new(&ShapeType::Square).size(size).position(x, y);
+        let names = Captioner::new();
+
+        // boxes meeting at a corner, and overlapping at one, with the circles
+        // well apart
+        for offset in [0.2, 0.18] {
+            let shapes = vec![
+                circle(0.1, 0.1, 0.2),
+                circle(0.1 + offset, 0.1 + offset, 0.2),
+            ];
+            let graph = SceneGraph::new(&Entry::new(shapes), 0.01, &names);
+            assert!(graph.related(0, SpatialRelation::Overlaps).is_empty());
+            assert!(graph.related(0, SpatialRelation::Touching).is_empty());
+        }
+
+        // side by side, the circles do touch
+        let shapes = vec![circle(0.1, 0.1, 0.2), circle(0.305, 0.1, 0.2)];
+        let graph = SceneGraph::new(&Entry::new(shapes), 0.01, &names);
+        assert_eq!(graph.related(0, SpatialRelation::Touching), vec![1]);
+
+        // a square in the corner of a circle's box is outside the circle
+        let shapes = vec![circle(0.1, 0.1, 0.4), square(0.1, 0.1, 0.05)];
+        let graph = SceneGraph::new(&Entry::new(shapes), 0.01, &names);
+        assert!(graph.related(0, SpatialRelation::Contains).is_empty());
+        assert!(graph.related(0, SpatialRelation::Overlaps).is_empty());
+
+        let shapes = vec![circle(0.1, 0.1, 0.4), square(0.28, 0.28, 0.05)];
+        let graph = SceneGraph::new(&Entry::new(shapes), 0.01, &names);
+        assert_eq!(graph.related(0, SpatialRelation::Contains), vec![1]);
+    }
+}
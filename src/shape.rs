@@ -1,42 +1,86 @@
 use rand::distributions::uniform::SampleUniform;
 use rand::seq::SliceRandom;
 use rand::Rng;
+use serde::{Serialize, Serializer};
+use std::fmt;
 use std::ops::{Add, Mul};
+use std::str::FromStr;
 
+use super::distribution::Distribution;
+use super::font;
+use super::obstacle::{closest_point_on_segment, Obstacle, ObstacleShape};
+use super::physics::{Boundary, Physics};
 use super::RangeOrSingle;
 
 use ndarray::prelude::*;
 use ndarray::Array3;
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ShapeType {
     Square,
     Circle,
+    Glyph(char),
+    /// Index into the sprites added with `Dataset::add_sprite`.
+    Sprite(usize),
 }
 
-pub trait NewRandom2<T: std::cmp::PartialOrd + SampleUniform + Copy> {
-    fn new(x: T, y: T) -> Self;
+impl ShapeType {
+    /// Glyphs for the digits 0-9, for Moving MNIST style datasets.
+    pub fn digits() -> Vec<ShapeType> {
+        ('0'..='9').map(ShapeType::Glyph).collect()
+    }
 
-    fn new_random(lower: T, upper: T) -> Self
-    where
-        Self: Sized,
-    {
-        let x = rand::thread_rng().gen_range(lower..=upper);
-        let y = rand::thread_rng().gen_range(lower..=upper);
+    /// Glyphs for the uppercase letters A-Z.
+    pub fn letters() -> Vec<ShapeType> {
+        ('A'..='Z').map(ShapeType::Glyph).collect()
+    }
+}
 
-        Self::new(x, y)
+impl fmt::Display for ShapeType {
+    /// Names shapes as `FromStr` parses them, with sprites as "sprite_<index>".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShapeType::Square => write!(f, "square"),
+            ShapeType::Circle => write!(f, "circle"),
+            ShapeType::Glyph(c) => write!(f, "{c}"),
+            ShapeType::Sprite(i) => write!(f, "sprite_{i}"),
+        }
     }
+}
 
-    fn new_from_range_or_single(range_or_single: &RangeOrSingle<T>) -> Self
-    where
-        Self: Sized,
-    {
-        match range_or_single {
-            RangeOrSingle::Range(l, u) => Self::new_random(*l, *u),
-            RangeOrSingle::Single(v) => Self::new(*v, *v),
+impl Serialize for ShapeType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl FromStr for ShapeType {
+    type Err = String;
+
+    /// Parses "square", "circle", "sprite_<index>", or a single character
+    /// with a glyph in the embedded font.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "square" => Ok(ShapeType::Square),
+            "circle" => Ok(ShapeType::Circle),
+            _ if s.starts_with("sprite_") => s["sprite_".len()..]
+                .parse()
+                .map(ShapeType::Sprite)
+                .map_err(|_| format!("Unknown shape type '{s}'")),
+            _ => {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if font::glyph_bitmap(c).is_some() => Ok(ShapeType::Glyph(c)),
+                    _ => Err(format!("Unknown shape type '{s}'")),
+                }
+            }
         }
     }
 }
 
+pub trait NewRandom2<T: std::cmp::PartialOrd + SampleUniform + Copy> {
+    fn new(x: T, y: T) -> Self;
+}
+
 pub trait NewRandom1<T: std::cmp::PartialOrd + SampleUniform + Copy> {
     fn new(v: T) -> Self;
 
@@ -60,7 +104,7 @@ pub trait NewRandom1<T: std::cmp::PartialOrd + SampleUniform + Copy> {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub struct Color(pub u8, pub u8, pub u8);
 
 impl Color {
@@ -68,13 +112,27 @@ impl Color {
         Color(r, g, b)
     }
 
-    pub fn new_random_from_palette(palette: &Vec<Color>) -> Self {
+    pub fn new_random_from_palette(palette: &[Color]) -> Self {
         *palette
             .choose(&mut rand::thread_rng())
             .expect("Failed picking random element from color palette")
     }
 
-    pub fn to_ndarray(&self) -> Array3<f64> {
+    pub fn channel(self, c: usize) -> f64 {
+        let value = match c {
+            0 => self.0,
+            1 => self.1,
+            2 => self.2,
+            _ => panic!("Color channel index out of range"),
+        };
+        (value as f64) / 255.
+    }
+
+    pub fn to_rgb(self) -> [f64; 3] {
+        [0, 1, 2].map(|c| self.channel(c))
+    }
+
+    pub fn to_ndarray(self) -> Array3<f64> {
         array![
             [[(self.0 as f64) / 255.,]],
             [[(self.1 as f64) / 255.,]],
@@ -82,11 +140,85 @@ impl Color {
         ]
     }
 }
-#[derive(Debug, Copy, Clone)]
+/// How a shape is drawn. Stroke widths are relative to the image size, like
+/// `Size`.
+#[derive(Debug, Copy, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FillStyle {
+    Filled,
+    Outline(f64),
+    /// Filled with the shape's color and outlined with a separate color.
+    FilledOutline(f64, Color),
+}
+
+/// Pattern mixing a shape's color with its secondary color. Textures are
+/// evaluated in shape-local coordinates, so they move with the shape. Angles
+/// are the direction the pattern varies along, in radians, with 0 running
+/// left to right. Frequencies are repetitions across the shape's width.
+/// Shapes have no rotation, so textures do not rotate either; orient
+/// patterns with their angle instead.
+#[derive(Debug, Copy, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Texture {
+    Solid,
+    Stripes {
+        angle: f64,
+        frequency: f64,
+    },
+    Checker {
+        frequency: f64,
+    },
+    /// Dots with `radius` relative to the spacing between them, in [0, 0.5].
+    Dots {
+        frequency: f64,
+        radius: f64,
+    },
+    Gradient {
+        angle: f64,
+    },
+}
+
+impl Texture {
+    /// Weight of the secondary color at local coordinates `(u, v)` in [0, 1).
+    pub fn weight(&self, u: f64, v: f64) -> f64 {
+        let along = |angle: f64| (u - 0.5) * angle.cos() + (v - 0.5) * angle.sin();
+
+        match *self {
+            Texture::Solid => 0.,
+            Texture::Stripes { angle, frequency } => {
+                if (along(angle) * frequency).rem_euclid(1.) < 0.5 {
+                    0.
+                } else {
+                    1.
+                }
+            }
+            Texture::Checker { frequency } => {
+                let cell = (u * frequency).floor() + (v * frequency).floor();
+                cell.rem_euclid(2.)
+            }
+            Texture::Dots { frequency, radius } => {
+                let xd = (u * frequency).fract() - 0.5;
+                let yd = (v * frequency).fract() - 0.5;
+                if (xd * xd + yd * yd).sqrt() <= radius {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            Texture::Gradient { angle } => {
+                // the projection of the unit box onto the gradient direction
+                let extent = angle.cos().abs() + angle.sin().abs();
+                (along(angle) / extent + 0.5).clamp(0., 1.)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize)]
 pub struct Position(pub f64, pub f64);
 impl NewRandom2<f64> for Position {
     fn new(x: f64, y: f64) -> Self {
-        if x < 0.0 || x > 1.0 || y < 0.0 || y > 1.0 {
+        if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
             panic!("Specified position was out of range [0, 1]. Got {x},{y}");
         }
 
@@ -94,8 +226,37 @@ impl NewRandom2<f64> for Position {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct Velocity(f64, f64);
+/// The point of a shape's bounding box that sampled positions refer to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    TopLeft,
+    Center,
+}
+
+impl Position {
+    /// Top-left position of a shape of `size` whose `anchor` point is drawn
+    /// from `distribution` on both axes, restricted to points where the shape
+    /// starts fully inside the image.
+    pub fn new_in_frame(distribution: &Distribution, anchor: Anchor, size: f64) -> Self {
+        let offset = match anchor {
+            Anchor::TopLeft => 0.,
+            Anchor::Center => size / 2.,
+        };
+        // anchor points keeping the shape in frame, or at the top-left corner
+        // for shapes bigger than the image
+        let (min, max) = (offset, (1. - size).max(0.) + offset);
+
+        let mut rng = rand::thread_rng();
+        let mut sample = || (distribution.sample_within(&mut rng, min, max) - offset).clamp(0., 1.);
+
+        let x = sample();
+        let y = sample();
+        Position::new(x, y)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize)]
+pub struct Velocity(pub f64, pub f64);
 
 impl NewRandom2<f64> for Velocity {
     fn new(x: f64, y: f64) -> Self {
@@ -103,6 +264,54 @@ impl NewRandom2<f64> for Velocity {
     }
 }
 
+/// How the starting velocities of random shapes are drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VelocityDistribution {
+    /// Both components drawn independently from the same distribution.
+    Cartesian(Distribution),
+    /// A speed, redrawn while negative, and a direction in radians from the
+    /// x axis towards the y axis, i.e. clockwise in the image. A `Choice` of
+    /// directions restricts shapes to a set of headings.
+    Polar {
+        speed: Distribution,
+        direction: Distribution,
+    },
+}
+
+impl VelocityDistribution {
+    /// Polar velocities heading in any direction.
+    pub fn isotropic(speed: Distribution) -> Self {
+        VelocityDistribution::Polar {
+            speed,
+            direction: Distribution::uniform(0., 2. * std::f64::consts::PI),
+        }
+    }
+
+    /// Checks the underlying distributions, as in `Distribution::validate`.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            VelocityDistribution::Cartesian(distribution) => distribution.validate(),
+            VelocityDistribution::Polar { speed, direction } => {
+                speed.validate()?;
+                direction.validate()
+            }
+        }
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Velocity {
+        match self {
+            VelocityDistribution::Cartesian(distribution) => {
+                Velocity(distribution.sample(rng), distribution.sample(rng))
+            }
+            VelocityDistribution::Polar { speed, direction } => {
+                let speed = speed.sample_within(rng, 0., f64::MAX);
+                let direction = direction.sample(rng);
+                Velocity(speed * direction.cos(), speed * direction.sin())
+            }
+        }
+    }
+}
+
 impl Add<Velocity> for Position {
     type Output = Position;
 
@@ -119,7 +328,7 @@ impl Mul<f64> for Velocity {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Size(pub f64);
 
 impl NewRandom1<f64> for Size {
@@ -128,23 +337,172 @@ impl NewRandom1<f64> for Size {
     }
 }
 
-#[derive(Debug)]
+/// Bounces resolved in a single step before giving up and clamping the shape
+/// to the walls. Only reachable with restitution just below 1 and huge speeds.
+const MAX_BOUNCES: usize = 10_000;
+
+/// Bounces a coordinate between walls at `min` and `max` until it is back in
+/// range, however far past the walls a step took it. Bounces lose a fraction
+/// of both the velocity and the distance travelled past the wall. Returns the
+/// new position and velocity.
+fn reflect_axis(position: f64, velocity: f64, min: f64, max: f64, restitution: f64) -> (f64, f64) {
+    let width = max - min;
+    if width <= 0.0 {
+        // no room to move between the walls
+        return (min, velocity);
+    }
+
+    if restitution == 1.0 {
+        // elastic bounces repeat every two widths travelled
+        let offset = position - min;
+        let folded = offset.rem_euclid(2.0 * width);
+        let position = if folded > width {
+            min + 2.0 * width - folded
+        } else {
+            min + folded
+        };
+        let bounces = (offset / width).floor();
+        let velocity = if bounces.rem_euclid(2.0) == 1.0 {
+            -velocity
+        } else {
+            velocity
+        };
+        return (position.clamp(min, max), velocity);
+    }
+
+    let (mut position, mut velocity) = (position, velocity);
+    for _ in 0..MAX_BOUNCES {
+        if position < min {
+            // hit left or top border
+            position = min + (min - position) * restitution;
+        } else if position > max {
+            // hit right or bottom border
+            position = max - (position - max) * restitution;
+        } else {
+            return (position, velocity);
+        }
+        velocity = -velocity * restitution;
+    }
+    (position.clamp(min, max), velocity)
+}
+
+/// Substeps a single step is split into at most, however fast the shape or
+/// thin the obstacles. Only reachable with tiny shapes at huge speeds.
+const MAX_SUBSTEPS: usize = 1_000_000;
+
+/// Pushes a coordinate out of the interval [low, high] occupied by an obstacle,
+/// back to the side it entered from, bouncing like `reflect_axis`.
+fn push_out(
+    position: f64,
+    velocity: f64,
+    size: f64,
+    low: f64,
+    high: f64,
+    restitution: f64,
+) -> (f64, f64) {
+    let from_low = if velocity != 0.0 {
+        velocity > 0.0
+    } else {
+        position + size / 2.0 < (low + high) / 2.0
+    };
+
+    let position = if from_low {
+        let penetration = position + size - low;
+        low - size - penetration * restitution
+    } else {
+        let penetration = high - position;
+        high + penetration * restitution
+    };
+    (position, -velocity * restitution)
+}
+
+#[derive(Debug, Serialize)]
+pub struct Mass(pub f64);
+
+impl NewRandom1<f64> for Mass {
+    fn new(mass: f64) -> Self {
+        if mass <= 0.0 {
+            panic!("Specified mass must be positive. Got {mass}");
+        }
+
+        Self(mass)
+    }
+}
+
+/// Parameters replacing a `Dataset`'s own for shapes of one type. Anything
+/// left unset falls back to the dataset's.
+#[derive(Debug, Clone, Default)]
+pub struct ShapeOverrides {
+    pub color_palette: Option<Vec<Color>>,
+    pub size: Option<Distribution>,
+    pub velocity: Option<VelocityDistribution>,
+    pub mass: Option<Distribution>,
+}
+
+impl ShapeOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_color(mut self, r: u8, g: u8, b: u8) -> Self {
+        self.color_palette
+            .get_or_insert_with(Vec::new)
+            .push(Color::new(r, g, b));
+        self
+    }
+
+    pub fn size(mut self, size: Distribution) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn velocity(mut self, velocity: Distribution) -> Self {
+        self.velocity = Some(VelocityDistribution::Cartesian(velocity));
+        self
+    }
+
+    pub fn polar_velocity(mut self, speed: Distribution, direction: Distribution) -> Self {
+        self.velocity = Some(VelocityDistribution::Polar { speed, direction });
+        self
+    }
+
+    pub fn mass(mut self, mass: Distribution) -> Self {
+        self.mass = Some(mass);
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct Shape {
     pub shape_type: ShapeType,
     pub color: Color,
+    pub secondary_color: Color,
+    pub texture: Texture,
+    pub fill_style: FillStyle,
     pub position: Position,
     pub velocity: Velocity,
     pub size: Size,
+    pub mass: Mass,
 }
 
 impl Shape {
     pub fn new(shape_type: &ShapeType) -> Self {
+        if let ShapeType::Glyph(c) = shape_type {
+            if font::glyph_bitmap(*c).is_none() {
+                panic!("No glyph in the embedded font for {c:?}");
+            }
+        }
+
         Shape {
             shape_type: *shape_type,
             color: Color::new(0xff, 0xff, 0xff),
+            secondary_color: Color::new(0xff, 0xff, 0xff),
+            texture: Texture::Solid,
+            fill_style: FillStyle::Filled,
             position: Position::new(0.0, 0.0),
             velocity: Velocity::new(0.0, 0.0),
             size: Size(1.0),
+            mass: Mass(1.0),
         }
     }
 
@@ -163,46 +521,486 @@ impl Shape {
         self
     }
 
+    pub fn texture(mut self, texture: Texture, r: u8, g: u8, b: u8) -> Self {
+        self.texture = texture;
+        self.secondary_color = Color::new(r, g, b);
+        self
+    }
+
+    /// Fill color at local coordinates `(u, v)` in [0, 1), with the texture
+    /// applied.
+    pub fn color_at(&self, u: f64, v: f64) -> [f64; 3] {
+        let weight = self.texture.weight(u, v);
+        let primary = self.color.to_rgb();
+        let secondary = self.secondary_color.to_rgb();
+        [0, 1, 2].map(|c| primary[c] * (1. - weight) + secondary[c] * weight)
+    }
+
+    pub fn fill_style(mut self, fill_style: FillStyle) -> Self {
+        self.fill_style = fill_style;
+        self
+    }
+
     pub fn size(mut self, size: f64) -> Self {
         self.size = Size(size);
         self
     }
 
-    fn handle_collision_square(&mut self) {
+    pub fn mass(mut self, mass: f64) -> Self {
+        self.mass = Mass::new(mass);
+        self
+    }
+
+    fn handle_collision_square(&mut self, restitution: f64) {
         let min = 0.0;
         let max = 1.0 - self.size.0;
 
-        if self.position.0 < min {
-            // hit left border
-            self.position.0 = self.position.0.abs();
-            self.velocity.0 = -self.velocity.0;
-        } else if self.position.0 > max {
-            // hit right border
-            self.position.0 = max - (self.position.0 - max);
-            self.velocity.0 = -self.velocity.0;
-        };
-        if self.position.1 < min {
-            // hit top border
-            self.position.1 = self.position.1.abs();
-            self.velocity.1 = -self.velocity.1;
-        } else if self.position.1 > max {
-            // hit bottom border
-            self.position.1 = max - (self.position.1 - max);
-            self.velocity.1 = -self.velocity.1;
+        (self.position.0, self.velocity.0) =
+            reflect_axis(self.position.0, self.velocity.0, min, max, restitution);
+        (self.position.1, self.velocity.1) =
+            reflect_axis(self.position.1, self.velocity.1, min, max, restitution);
+    }
+
+    fn handle_collision_circle(&mut self, restitution: f64) {
+        self.handle_collision_square(restitution);
+    }
+
+    fn handle_wrap(&mut self) {
+        self.position.0 = self.position.0.rem_euclid(1.0);
+        self.position.1 = self.position.1.rem_euclid(1.0);
+    }
+
+    fn handle_absorb(&mut self) {
+        let min = 0.0;
+        let max = 1.0 - self.size.0;
+
+        let x = self.position.0.clamp(min, max);
+        let y = self.position.1.clamp(min, max);
+        if x != self.position.0 || y != self.position.1 {
+            self.position = Position(x, y);
+            self.velocity = Velocity(0.0, 0.0);
+        }
+    }
+
+    fn apply_forces(&mut self, step_size: f64, physics: &Physics) {
+        let (gx, gy) = physics.gravity;
+        self.velocity.0 += gx * step_size;
+        self.velocity.1 += gy * step_size;
+
+        let damping = (1.0 - physics.drag * step_size / self.mass.0).max(0.0);
+        self.velocity = self.velocity * damping;
+    }
+
+    fn handle_collision_rectangle(&mut self, bounds: (f64, f64, f64, f64), restitution: f64) {
+        let (x1, y1, x2, y2) = bounds;
+        let size = self.size.0;
+
+        let overlap_x = (self.position.0 + size).min(x2) - self.position.0.max(x1);
+        let overlap_y = (self.position.1 + size).min(y2) - self.position.1.max(y1);
+        if overlap_x <= 0.0 || overlap_y <= 0.0 {
+            return;
+        }
+
+        // push out along the axis we penetrated least
+        if overlap_x < overlap_y {
+            (self.position.0, self.velocity.0) =
+                push_out(self.position.0, self.velocity.0, size, x1, x2, restitution);
+        } else {
+            (self.position.1, self.velocity.1) =
+                push_out(self.position.1, self.velocity.1, size, y1, y2, restitution);
+        }
+    }
+
+    fn handle_collision_segment(
+        &mut self,
+        start: (f64, f64),
+        end: (f64, f64),
+        thickness: f64,
+        restitution: f64,
+    ) {
+        // shapes collide with segments as their inscribed circle
+        let radius = self.size.0 / 2.0;
+        let center = (self.position.0 + radius, self.position.1 + radius);
+
+        let closest = closest_point_on_segment(start, end, center);
+        let (dx, dy) = (center.0 - closest.0, center.1 - closest.1);
+        let distance = (dx * dx + dy * dy).sqrt();
+        let reach = radius + thickness / 2.0;
+        if distance >= reach {
+            return;
+        }
+
+        let (nx, ny) = if distance > 0.0 {
+            (dx / distance, dy / distance)
+        } else {
+            // centered on the segment, so push back the way we came
+            let speed =
+                (self.velocity.0 * self.velocity.0 + self.velocity.1 * self.velocity.1).sqrt();
+            if speed == 0.0 {
+                return;
+            }
+            (-self.velocity.0 / speed, -self.velocity.1 / speed)
         };
+
+        let penetration = reach - distance;
+        self.position.0 += nx * penetration * (1.0 + restitution);
+        self.position.1 += ny * penetration * (1.0 + restitution);
+
+        let into_segment = self.velocity.0 * nx + self.velocity.1 * ny;
+        if into_segment < 0.0 {
+            self.velocity.0 -= (1.0 + restitution) * into_segment * nx;
+            self.velocity.1 -= (1.0 + restitution) * into_segment * ny;
+        }
     }
 
-    fn handle_collision_circle(&mut self) {
-        self.handle_collision_square();
+    fn handle_collision_obstacle(&mut self, obstacle: &Obstacle, restitution: f64) {
+        match obstacle.shape {
+            ObstacleShape::Rectangle { .. } => {
+                self.handle_collision_rectangle(obstacle.bounds(), restitution)
+            }
+            ObstacleShape::Segment {
+                start,
+                end,
+                thickness,
+            } => self.handle_collision_segment(start, end, thickness, restitution),
+        }
     }
 
-    pub fn step_shape(&mut self, step_size: f64) {
+    fn substep_shape(
+        &mut self,
+        step_size: f64,
+        physics: &Physics,
+        boundary: Boundary,
+        obstacles: &[Obstacle],
+    ) {
+        self.apply_forces(step_size, physics);
+
         // let mut new_position = self.position + self.velocity * step_size;
         self.position = self.position + self.velocity * step_size;
 
-        match self.shape_type {
-            ShapeType::Square => self.handle_collision_square(),
-            ShapeType::Circle => self.handle_collision_circle(),
+        let restitution = physics.restitution;
+        for obstacle in obstacles {
+            self.handle_collision_obstacle(obstacle, restitution);
+        }
+
+        match (boundary, self.shape_type) {
+            (Boundary::Reflect, ShapeType::Square) => self.handle_collision_square(restitution),
+            (Boundary::Reflect, ShapeType::Circle) => self.handle_collision_circle(restitution),
+            (Boundary::Reflect, ShapeType::Glyph(_) | ShapeType::Sprite(_)) => {
+                self.handle_collision_square(restitution)
+            }
+            (Boundary::Wrap, _) => self.handle_wrap(),
+            (Boundary::Open, _) => {}
+            (Boundary::Absorb, _) => self.handle_absorb(),
+        }
+    }
+
+    pub fn step_shape(
+        &mut self,
+        step_size: f64,
+        physics: &Physics,
+        boundary: Boundary,
+        obstacles: &[Obstacle],
+    ) {
+        // move at most half the shape's size or the thinnest obstacle's
+        // thickness per substep, so neither can jump over the other
+        let thinnest = obstacles
+            .iter()
+            .map(Obstacle::thickness)
+            .min_by(f64::total_cmp);
+        let substeps = match thinnest {
+            None => 1,
+            Some(thickness) => {
+                let speed =
+                    (self.velocity.0 * self.velocity.0 + self.velocity.1 * self.velocity.1).sqrt();
+                let max_travel = self.size.0.min(thickness) / 2.0;
+                ((speed * step_size / max_travel).ceil() as usize).clamp(1, MAX_SUBSTEPS)
+            }
+        };
+
+        let substep_size = step_size / substeps as f64;
+        for _ in 0..substeps {
+            self.substep_shape(substep_size, physics, boundary, obstacles);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObstacleType;
+
+    fn random_shape(rng: &mut impl Rng) -> Shape {
+        let size = rng.gen_range(0.01..0.5);
+        Shape::new(&ShapeType::Square)
+            .size(size)
+            .position(
+                rng.gen_range(0.0..=1.0 - size),
+                rng.gen_range(0.0..=1.0 - size),
+            )
+            .velocity(rng.gen_range(-1e3..1e3), rng.gen_range(-1e3..1e3))
+    }
+
+    fn assert_in_frame(shape: &Shape) {
+        let max = 1.0 - shape.size.0;
+        assert!(
+            (0.0..=max).contains(&shape.position.0) && (0.0..=max).contains(&shape.position.1),
+            "Shape left the frame: {shape:?}"
+        );
+    }
+
+    #[test]
+    fn texture_weights_follow_patterns() {
+        let stripes = Texture::Stripes {
+            angle: 0.,
+            frequency: 2.,
+        };
+        // stripes varying left to right are constant top to bottom
+        assert_eq!(stripes.weight(0.6, 0.1), stripes.weight(0.6, 0.9));
+        assert_ne!(stripes.weight(0.6, 0.5), stripes.weight(0.9, 0.5));
+
+        let checker = Texture::Checker { frequency: 2. };
+        assert_eq!(checker.weight(0.25, 0.25), 0.);
+        assert_eq!(checker.weight(0.75, 0.25), 1.);
+        assert_eq!(checker.weight(0.75, 0.75), 0.);
+
+        let dots = Texture::Dots {
+            frequency: 2.,
+            radius: 0.25,
+        };
+        assert_eq!(dots.weight(0.25, 0.25), 1.);
+        assert_eq!(dots.weight(0.5, 0.5), 0.);
+
+        let gradient = Texture::Gradient { angle: 0. };
+        assert_eq!(gradient.weight(0., 0.5), 0.);
+        assert!((gradient.weight(0.5, 0.1) - 0.5).abs() < 1e-12);
+        assert_eq!(gradient.weight(1., 0.5), 1.);
+
+        assert_eq!(Texture::Solid.weight(0.3, 0.7), 0.);
+    }
+
+    #[test]
+    fn single_bounce() {
+        let mut shape = Shape::new(&ShapeType::Square)
+            .size(0.1)
+            .position(0.85, 0.5)
+            .velocity(0.2, 0.0);
+        shape.step_shape(1.0, &Physics::new(), Boundary::Reflect, &[]);
+
+        assert!((shape.position.0 - 0.75).abs() < 1e-12);
+        assert_eq!(shape.velocity.0, -0.2);
+    }
+
+    #[test]
+    fn gravity_accelerates_by_g_dt() {
+        let mut shape = Shape::new(&ShapeType::Square)
+            .size(0.1)
+            .position(0.45, 0.2)
+            .velocity(0.1, 0.0);
+        let physics = Physics::new().gravity(0.0, 2.0);
+        shape.step_shape(0.1, &physics, Boundary::Reflect, &[]);
+
+        assert_eq!(shape.velocity.0, 0.1);
+        assert!((shape.velocity.1 - 0.2).abs() < 1e-12);
+        assert!((shape.position.1 - 0.22).abs() < 1e-12);
+    }
+
+    #[test]
+    fn inelastic_bounces_lose_speed() {
+        let mut shape = Shape::new(&ShapeType::Square)
+            .size(0.1)
+            .position(0.85, 0.5)
+            .velocity(0.2, 0.0);
+        let physics = Physics::new().restitution(0.5);
+        shape.step_shape(1.0, &physics, Boundary::Reflect, &[]);
+
+        // 0.15 past the wall at 0.9, bounced back half of that
+        assert!((shape.position.0 - 0.825).abs() < 1e-12);
+        assert!((shape.velocity.0 + 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn fast_elastic_shapes_stay_in_frame() {
+        let mut rng = rand::thread_rng();
+        let physics = Physics::new();
+
+        for _ in 0..1000 {
+            let mut shape = random_shape(&mut rng);
+            let speed = (shape.velocity.0.abs(), shape.velocity.1.abs());
+
+            for _ in 0..10 {
+                shape.step_shape(rng.gen_range(0.001..1.0), &physics, Boundary::Reflect, &[]);
+                assert_in_frame(&shape);
+                assert_eq!((shape.velocity.0.abs(), shape.velocity.1.abs()), speed);
+            }
+        }
+    }
+
+    #[test]
+    fn fast_inelastic_shapes_stay_in_frame() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..1000 {
+            let physics = Physics::new()
+                .gravity(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0))
+                .restitution(rng.gen_range(0.0..1.0));
+            let mut shape = random_shape(&mut rng);
+
+            for _ in 0..10 {
+                shape.step_shape(rng.gen_range(0.001..1.0), &physics, Boundary::Reflect, &[]);
+                assert_in_frame(&shape);
+            }
+        }
+    }
+
+    #[test]
+    fn shapes_do_not_pass_through_walls() {
+        let mut rng = rand::thread_rng();
+        let obstacles = [
+            Obstacle::rectangle(0.5, 0.0, 0.01, 1.0),
+            Obstacle::segment(0.0, 0.5, 0.5, 0.5, 0.01),
+        ];
+
+        for _ in 0..1000 {
+            let size = rng.gen_range(0.01..0.2);
+            let mut shape = Shape::new(&ShapeType::Circle)
+                .size(size)
+                .position(
+                    rng.gen_range(0.0..=0.5 - size),
+                    rng.gen_range(0.0..=0.5 - size),
+                )
+                .velocity(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0));
+
+            for _ in 0..10 {
+                shape.step_shape(0.1, &Physics::new(), Boundary::Reflect, &obstacles);
+                assert!(
+                    shape.position.0 + size <= 0.5 + 1e-9,
+                    "Passed through: {shape:?}"
+                );
+                assert!(
+                    shape.position.1 + size / 2.0 <= 0.5,
+                    "Passed through: {shape:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn small_fast_shapes_do_not_pass_through_thin_walls() {
+        let obstacles = [Obstacle::rectangle(0.5, 0.0, 0.001, 1.0)];
+        let mut shape = Shape::new(&ShapeType::Square)
+            .size(0.001)
+            .position(0.25, 0.5)
+            .velocity(100.0, 0.0);
+
+        for _ in 0..10 {
+            shape.step_shape(1.0, &Physics::new(), Boundary::Reflect, &obstacles);
+            assert!(shape.position.0 + 0.001 <= 0.5, "Passed through: {shape:?}");
+        }
+    }
+
+    #[test]
+    fn substeps_are_capped_for_tiny_shapes() {
+        let obstacles = [Obstacle::rectangle(0.5, 0.0, 0.01, 1.0)];
+        let mut shape = Shape::new(&ShapeType::Square)
+            .size(f64::MIN_POSITIVE)
+            .position(0.25, 0.5)
+            .velocity(1.0, 0.0);
+
+        shape.step_shape(0.1, &Physics::new(), Boundary::Reflect, &obstacles);
+        assert!(shape.position.0 > 0.25);
+    }
+
+    #[test]
+    fn long_random_segments_stay_in_frame() {
+        for _ in 0..1000 {
+            let obstacle =
+                Obstacle::new_random(ObstacleType::Segment, &RangeOrSingle::Range(0.9, 1.5));
+            let ObstacleShape::Segment { start, end, .. } = obstacle.shape else {
+                panic!("Expected a segment: {obstacle:?}");
+            };
+            for coordinate in [start.0, start.1, end.0, end.1] {
+                assert!((-1e-12..=1.0 + 1e-12).contains(&coordinate), "{obstacle:?}");
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "thickness must be positive")]
+    fn flat_segments_are_rejected() {
+        Obstacle::segment(0.5, 0.0, 0.5, 1.0, 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "width and height must be positive")]
+    fn flat_rectangles_are_rejected() {
+        Obstacle::rectangle(0.5, 0.0, 0.0, 1.0);
+    }
+
+    #[test]
+    fn sampled_positions_start_in_frame() {
+        let mut rng = rand::thread_rng();
+        let ranges = [
+            Distribution::uniform(0.0, 1.0),
+            Distribution::uniform(0.9, 1.0),
+            Distribution::constant(0.0),
+            Distribution::Normal {
+                mean: 0.5,
+                std: 1.0,
+            },
+        ];
+
+        for _ in 0..1000 {
+            let size = rng.gen_range(0.01..1.0);
+            for range in &ranges {
+                for anchor in [Anchor::TopLeft, Anchor::Center] {
+                    let Position(x, y) = Position::new_in_frame(range, anchor, size);
+                    assert!(x >= 0. && x + size <= 1. + 1e-9, "{x} with size {size}");
+                    assert!(y >= 0. && y + size <= 1. + 1e-9, "{y} with size {size}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn overrides_apply_per_shape_type() {
+        let dataset = crate::Dataset::new()
+            .shape_types(vec![ShapeType::Square, ShapeType::Circle])
+            .add_color(0, 0, 255)
+            .size(0.3)
+            .num_shapes(10)
+            .override_shape_type(
+                ShapeType::Circle,
+                ShapeOverrides::new()
+                    .add_color(255, 0, 0)
+                    .size(Distribution::constant(0.1)),
+            );
+
+        for shape in dataset.generate_random_entry().shapes() {
+            let (color, size) = match shape.shape_type {
+                ShapeType::Circle => (Color(255, 0, 0), 0.1),
+                _ => (Color(0, 0, 255), 0.3),
+            };
+            assert_eq!(shape.color, color);
+            assert_eq!(shape.size.0, size);
+        }
+    }
+
+    #[test]
+    fn polar_velocities_keep_speed_and_direction() {
+        let mut rng = rand::thread_rng();
+        let velocity = VelocityDistribution::Polar {
+            speed: Distribution::constant(0.5),
+            direction: Distribution::Choice {
+                values: vec![0., std::f64::consts::FRAC_PI_2],
+                weights: vec![],
+            },
+        };
+
+        for _ in 0..100 {
+            let Velocity(x, y) = velocity.sample(&mut rng);
+            assert!((x.hypot(y) - 0.5).abs() < 1e-12);
+            assert!(x.abs() < 1e-12 || y.abs() < 1e-12, "{x},{y} is diagonal");
         }
     }
 }
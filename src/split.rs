@@ -0,0 +1,198 @@
+use super::shape::{Color, ShapeType};
+use std::error::Error;
+use std::fmt;
+
+/// Shapes to hold out of training, matched on their type, primary color and
+/// size. Attributes left unset match any shape.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Combination {
+    pub shape_type: Option<ShapeType>,
+    pub color: Option<Color>,
+    /// Sizes in [low, high).
+    pub size: Option<(f64, f64)>,
+}
+
+impl Combination {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn shape_type(mut self, shape_type: ShapeType) -> Self {
+        self.shape_type = Some(shape_type);
+        self
+    }
+
+    pub fn color(mut self, r: u8, g: u8, b: u8) -> Self {
+        self.color = Some(Color::new(r, g, b));
+        self
+    }
+
+    pub fn size_bucket(mut self, low: f64, high: f64) -> Self {
+        if low >= high {
+            panic!("Size bucket lower bound must be below the upper bound. Got {low}, {high}");
+        }
+
+        self.size = Some((low, high));
+        self
+    }
+
+    pub fn matches(&self, shape_type: ShapeType, color: Color, size: f64) -> bool {
+        self.shape_type.is_none_or(|t| t == shape_type)
+            && self.color.is_none_or(|c| c == color)
+            && self
+                .size
+                .is_none_or(|(low, high)| (low..high).contains(&size))
+    }
+}
+
+/// Which part of a `Split` a dataset generates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitMode {
+    /// Only shapes matching none of the held out combinations.
+    Train,
+    /// Only shapes matching a validation combination and no test one.
+    Val,
+    /// Only shapes matching a test combination.
+    Test,
+}
+
+/// A compositional hold-out split, e.g. red circles only ever appearing at
+/// test time. The same split with a different mode gives each part, so train,
+/// validation and test datasets are built from one config. Without held out
+/// combinations, every shape is in the training part.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    mode: SplitMode,
+    val: Vec<Combination>,
+    test: Vec<Combination>,
+}
+
+impl Split {
+    pub fn new() -> Self {
+        Split {
+            mode: SplitMode::Train,
+            val: Vec::new(),
+            test: Vec::new(),
+        }
+    }
+
+    pub fn mode(mut self, mode: SplitMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn add_val(mut self, combination: Combination) -> Self {
+        self.val.push(combination);
+        self
+    }
+
+    pub fn add_test(mut self, combination: Combination) -> Self {
+        self.test.push(combination);
+        self
+    }
+
+    pub fn get_mode(&self) -> SplitMode {
+        self.mode
+    }
+
+    /// Whether a shape with these attributes may appear in the split's mode.
+    pub fn accepts(&self, shape_type: ShapeType, color: Color, size: f64) -> bool {
+        let matches = |combinations: &[Combination]| {
+            combinations
+                .iter()
+                .any(|c| c.matches(shape_type, color, size))
+        };
+
+        match self.mode {
+            SplitMode::Train => !matches(&self.val) && !matches(&self.test),
+            SplitMode::Val => matches(&self.val) && !matches(&self.test),
+            SplitMode::Test => matches(&self.test),
+        }
+    }
+}
+
+/// Returned when no shape the split allows was drawn within the retry
+/// budget, which usually means none of the shape types, colors and sizes the
+/// dataset generates fall in the split's mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitError {
+    pub mode: SplitMode,
+    pub tries: usize,
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "No shape allowed in the {:?} split after {} tries",
+            self.mode, self.tries
+        )
+    }
+}
+
+impl Error for SplitError {}
+
+impl Default for Split {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Dataset, GenerationError};
+
+    fn dataset(mode: SplitMode) -> Dataset {
+        let split = Split::new()
+            .add_test(
+                Combination::new()
+                    .shape_type(ShapeType::Circle)
+                    .color(255, 0, 0),
+            )
+            .add_val(Combination::new().size_bucket(0.0, 0.1))
+            .add_val(Combination::new().shape_type(ShapeType::Circle))
+            .mode(mode);
+
+        Dataset::new()
+            .shape_types(vec![ShapeType::Square, ShapeType::Circle])
+            .add_color(255, 0, 0)
+            .add_color(0, 0, 255)
+            .size_range(0.05, 0.2)
+            .num_shapes(10)
+            .split(split)
+    }
+
+    #[test]
+    fn modes_partition_shapes() {
+        let red = Color::new(255, 0, 0);
+        for _ in 0..10 {
+            for shape in dataset(SplitMode::Train).generate_random_entry().shapes() {
+                assert!(shape.size.0 >= 0.1);
+                assert_eq!(shape.shape_type, ShapeType::Square);
+            }
+            for shape in dataset(SplitMode::Val).generate_random_entry().shapes() {
+                assert!(shape.size.0 < 0.1 || shape.shape_type == ShapeType::Circle);
+                assert!(!(shape.shape_type == ShapeType::Circle && shape.color == red));
+            }
+            for shape in dataset(SplitMode::Test).generate_random_entry().shapes() {
+                assert!(shape.shape_type == ShapeType::Circle && shape.color == red);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_modes_are_errors() {
+        let split = Split::new()
+            .add_test(Combination::new().color(0, 255, 0))
+            .mode(SplitMode::Test);
+        let dataset = Dataset::new()
+            .shape_types(vec![ShapeType::Square])
+            .add_color(255, 0, 0)
+            .num_shapes(1)
+            .split(split);
+
+        let error = dataset.try_generate_random_entry().unwrap_err();
+        assert!(matches!(error, GenerationError::Split(_)));
+    }
+}
@@ -0,0 +1,104 @@
+use std::fmt;
+use std::path::Path;
+
+use image::{ImageResult, RgbaImage};
+
+/// An RGBA image drawn as a shape by `ShapeType::Sprite`.
+pub struct Sprite {
+    image: RgbaImage,
+    tint: bool,
+}
+
+impl Sprite {
+    pub fn new(image: RgbaImage) -> Self {
+        Sprite { image, tint: false }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        Ok(Sprite::new(image::open(path)?.into_rgba8()))
+    }
+
+    /// Multiply the sprite by the shape's palette color when rendering.
+    pub fn tint(mut self, tint: bool) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn is_tinted(&self) -> bool {
+        self.tint
+    }
+
+    /// Samples the sprite at `(u, v)`, given in [0, 1) relative to the shape's
+    /// square bounding box. The image is scaled to fit the box, keeping its
+    /// aspect ratio, and centered. Returns RGBA in [0, 1], or `None` outside
+    /// the image.
+    pub fn sample(&self, u: f64, v: f64) -> Option<[f64; 4]> {
+        let (width, height) = self.image.dimensions();
+        let longest = width.max(height) as f64;
+
+        let x = u * longest - (longest - width as f64) / 2.;
+        let y = v * longest - (longest - height as f64) / 2.;
+        if x < 0.0 || y < 0.0 || x >= width as f64 || y >= height as f64 {
+            return None;
+        }
+
+        let pixel = self.image.get_pixel(x as u32, y as u32);
+        Some(pixel.0.map(|c| (c as f64) / 255.))
+    }
+}
+
+impl fmt::Debug for Sprite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sprite")
+            .field("dimensions", &self.image.dimensions())
+            .field("tint", &self.tint)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Dataset, ShapeType};
+    use image::Rgba;
+
+    fn sprite() -> Sprite {
+        // 2x1, opaque red on the left and transparent on the right
+        let mut image = RgbaImage::new(2, 1);
+        image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        image.put_pixel(1, 0, Rgba([0, 0, 0, 0]));
+        Sprite::new(image)
+    }
+
+    #[test]
+    fn samples_are_letterboxed() {
+        let sprite = sprite();
+        assert_eq!(sprite.sample(0.25, 0.5), Some([1., 0., 0., 1.]));
+        assert_eq!(sprite.sample(0.75, 0.5), Some([0., 0., 0., 0.]));
+        // the image is centered vertically in the square box
+        assert_eq!(sprite.sample(0.25, 0.1), None);
+    }
+
+    #[test]
+    fn sprites_render_with_alpha() {
+        let dataset = Dataset::new()
+            .add_sprite(sprite())
+            .shape_types(vec![ShapeType::Sprite(0)])
+            .add_color(0, 0, 255)
+            .size(1.0)
+            .num_shapes(1);
+        let image = dataset.get_random_image_array(8);
+
+        assert_eq!(image[[0, 4, 1]], 1.);
+        assert_eq!(image[[0, 4, 6]], 0.);
+        assert_eq!(image[[0, 0, 1]], 0.);
+    }
+
+    #[test]
+    #[should_panic(expected = "No sprite 1")]
+    fn missing_sprites_are_rejected() {
+        Dataset::new()
+            .add_sprite(sprite())
+            .shape_types(vec![ShapeType::Sprite(1)]);
+    }
+}
@@ -0,0 +1,126 @@
+use std::io::{self, Write};
+
+const BLOCK_SIZE: usize = 512;
+
+/// Writes POSIX ustar archives of regular files, one after the other.
+pub struct TarWriter<W: Write> {
+    writer: W,
+    bytes_written: u64,
+}
+
+/// Writes `value` as a NUL-terminated octal number filling `field`, or fails
+/// if it has too many digits to fit.
+fn write_octal(field: &mut [u8], value: u64) -> io::Result<()> {
+    let digits = format!("{value:0width$o}", width = field.len() - 1);
+    if digits.len() >= field.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{value} does not fit in a {} byte tar field", field.len()),
+        ));
+    }
+
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+    Ok(())
+}
+
+impl<W: Write> TarWriter<W> {
+    pub fn new(writer: W) -> Self {
+        TarWriter {
+            writer,
+            bytes_written: 0,
+        }
+    }
+
+    /// Bytes written so far, including headers and padding.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Size in the archive of a file holding `len` bytes.
+    pub fn entry_size(len: usize) -> u64 {
+        (BLOCK_SIZE + len.div_ceil(BLOCK_SIZE) * BLOCK_SIZE) as u64
+    }
+
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        if name.len() > 100 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("File name too long for a tar archive: {name}"),
+            ));
+        }
+
+        let mut header = [0u8; BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        write_octal(&mut header[100..108], 0o644)?;
+        write_octal(&mut header[108..116], 0)?; // uid
+        write_octal(&mut header[116..124], 0)?; // gid
+        write_octal(&mut header[124..136], data.len() as u64)?;
+        write_octal(&mut header[136..148], 0)?; // mtime
+        header[156] = b'0'; // regular file
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+
+        // the checksum is computed with its own field filled with spaces
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+        write_octal(&mut header[148..155], checksum as u64)?;
+
+        let padding = data.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE - data.len();
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)?;
+        self.writer.write_all(&[0; BLOCK_SIZE][..padding])?;
+        self.bytes_written += Self::entry_size(data.len());
+        Ok(())
+    }
+
+    /// Writes the end of archive marker, returning the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&[0; 2 * BLOCK_SIZE])?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn octal(field: &[u8]) -> u64 {
+        let digits = std::str::from_utf8(field).unwrap();
+        u64::from_str_radix(digits.trim_end_matches(['\0', ' ']), 8).unwrap()
+    }
+
+    #[test]
+    fn entries_are_checksummed_and_padded() {
+        let mut tar = TarWriter::new(Vec::new());
+        tar.add_file("a.txt", b"hello").unwrap();
+        assert_eq!(tar.bytes_written(), 2 * BLOCK_SIZE as u64);
+        let bytes = tar.finish().unwrap();
+        assert_eq!(bytes.len(), 4 * BLOCK_SIZE);
+
+        let header = &bytes[..BLOCK_SIZE];
+        assert_eq!(&header[..6], b"a.txt\0");
+        assert_eq!(octal(&header[124..136]), 5);
+        // the checksum sums the header with its own field as spaces
+        let unsigned_sum: u64 = header
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b } as u64)
+            .sum();
+        assert_eq!(octal(&header[148..156]), unsigned_sum);
+
+        assert_eq!(&bytes[BLOCK_SIZE..BLOCK_SIZE + 5], b"hello");
+        assert!(bytes[BLOCK_SIZE + 5..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn oversized_values_are_errors() {
+        let mut field = [0u8; 8];
+        assert!(write_octal(&mut field, 0o7777777).is_ok());
+        assert_eq!(
+            write_octal(&mut field, 0o10000000).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+}
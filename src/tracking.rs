@@ -0,0 +1,132 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+/// Ground truth for one shape in one frame of a video. Ids are the index of
+/// the shape in its entry, so they stay the same for the whole video.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackAnnotation {
+    pub frame: usize,
+    pub id: usize,
+    /// Top-left corner of the shape, in image units.
+    pub position: (f64, f64),
+    /// In image units per unit time.
+    pub velocity: (f64, f64),
+    /// Tight box around the drawn shape as (left, top, width, height) in
+    /// pixels. It may extend past the image for shapes leaving the frame.
+    pub bbox: (f64, f64, f64, f64),
+    /// Fraction of the shape's pixels that are on screen and not hidden by
+    /// shapes drawn over it.
+    pub visibility: f64,
+}
+
+/// Formats tracks can be saved in.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum TrackFormat {
+    /// MOTChallenge text, as written by `write_mot`.
+    Mot,
+    /// A JSON array, as written by `write_json`.
+    Json,
+}
+
+impl TrackFormat {
+    /// Picks the format from a file extension: "txt" for MOTChallenge and
+    /// "json" for JSON.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "txt" => Some(TrackFormat::Mot),
+            "json" => Some(TrackFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            TrackFormat::Mot => "txt",
+            TrackFormat::Json => "json",
+        }
+    }
+}
+
+/// Writes annotations as MOTChallenge ground truth, one line per shape per
+/// frame: `frame,id,bb_left,bb_top,bb_width,bb_height,1,-1,-1,-1`, with a
+/// confidence of 1 and no world coordinates. Frames and ids are 1-based, as
+/// the format expects. Visibility is only kept by `write_json`.
+pub fn write_mot<W: Write>(mut writer: W, annotations: &[TrackAnnotation]) -> io::Result<()> {
+    for annotation in annotations {
+        let (left, top, width, height) = annotation.bbox;
+        writeln!(
+            writer,
+            "{},{},{left},{top},{width},{height},1,-1,-1,-1",
+            annotation.frame + 1,
+            annotation.id + 1,
+        )?;
+    }
+    writer.flush()
+}
+
+/// Writes annotations as a JSON array, keeping the 0-based frames and ids.
+pub fn write_json<W: Write>(mut writer: W, annotations: &[TrackAnnotation]) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut writer, annotations)?;
+    writer.flush()
+}
+
+/// Saves annotations, picking the format from the extension of `path`.
+pub fn save_tracks(path: impl AsRef<Path>, annotations: &[TrackAnnotation]) -> io::Result<()> {
+    let path = path.as_ref();
+    let format = TrackFormat::from_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown track format for {}", path.display()),
+        )
+    })?;
+
+    let writer = BufWriter::new(File::create(path)?);
+    match format {
+        TrackFormat::Mot => write_mot(writer, annotations),
+        TrackFormat::Json => write_json(writer, annotations),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotations() -> Vec<TrackAnnotation> {
+        (0..2)
+            .map(|frame| TrackAnnotation {
+                frame,
+                id: 0,
+                position: (0.25, 0.5),
+                velocity: (0.1, 0.),
+                bbox: (2. + frame as f64, 4., 3., 3.),
+                visibility: 0.5,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mot_lines_follow_the_format() {
+        let mut output = Vec::new();
+        write_mot(&mut output, &annotations()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "1,1,2,4,3,3,1,-1,-1,-1\n2,1,3,4,3,3,1,-1,-1,-1\n"
+        );
+    }
+
+    #[test]
+    fn json_keeps_zero_based_frames() {
+        let mut output = Vec::new();
+        write_json(&mut output, &annotations()).unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json[1]["frame"], 1);
+        assert_eq!(json[1]["id"], 0);
+        assert_eq!(json[1]["visibility"], 0.5);
+    }
+}
@@ -0,0 +1,294 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame};
+use ndarray::Array4;
+
+use super::ndarray_to_image;
+
+/// Formats videos can be saved in.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum VideoFormat {
+    Gif,
+    Apng,
+    /// Uncompressed YUV4MPEG2 with 4:2:0 chroma.
+    Y4m,
+    /// Headerless interleaved 8-bit RGB frames, one after the other.
+    Raw,
+}
+
+impl VideoFormat {
+    /// Picks the format from a file extension: "gif" for GIF, "png" or
+    /// "apng" for APNG, "y4m" for Y4M and "rgb" or "raw" for raw RGB.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "gif" => Some(VideoFormat::Gif),
+            "png" | "apng" => Some(VideoFormat::Apng),
+            "y4m" => Some(VideoFormat::Y4m),
+            "rgb" | "raw" => Some(VideoFormat::Raw),
+            _ => None,
+        }
+    }
+
+    /// The usual extension of files in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            VideoFormat::Gif => "gif",
+            VideoFormat::Apng => "png",
+            VideoFormat::Y4m => "y4m",
+            VideoFormat::Raw => "rgb",
+        }
+    }
+}
+
+/// Checks that `video` is (frames, 3, size, size), as the writers expect.
+pub(crate) fn check_video(video: &Array4<f64>) -> io::Result<()> {
+    let (_, channels, height, width) = video.dim();
+    if channels != 3 || height != width {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Videos must be (frames, 3, size, size), got {:?}",
+                video.shape()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Frames of a (frames, 3, size, size) video as 8-bit RGB buffers.
+fn video_frames(video: &Array4<f64>) -> io::Result<impl Iterator<Item = image::RgbImage> + '_> {
+    check_video(video)?;
+    let (_, _, _, size) = video.dim();
+
+    Ok(video
+        .outer_iter()
+        .map(move |frame| ndarray_to_image(frame.to_owned(), size as u32)))
+}
+
+/// Writes a (frames, 3, size, size) video as an endlessly looping GIF,
+/// showing each frame for `frame_delay_ms` milliseconds.
+pub fn write_gif<W: Write>(writer: W, video: &Array4<f64>, frame_delay_ms: u16) -> io::Result<()> {
+    let mut encoder = GifEncoder::new(writer);
+    encoder
+        .set_repeat(Repeat::Infinite)
+        .map_err(io::Error::other)?;
+
+    let delay = Delay::from_numer_denom_ms(frame_delay_ms as u32, 1);
+    let frames = video_frames(video)?.map(|frame| {
+        let frame = DynamicImage::ImageRgb8(frame).into_rgba8();
+        Frame::from_parts(frame, 0, 0, delay)
+    });
+    encoder.encode_frames(frames).map_err(io::Error::other)
+}
+
+/// Writes a (frames, 3, size, size) video as an endlessly looping APNG,
+/// showing each frame for `frame_delay_ms` milliseconds.
+pub fn write_apng<W: Write>(writer: W, video: &Array4<f64>, frame_delay_ms: u16) -> io::Result<()> {
+    let frames = video_frames(video)?;
+    let (num_frames, _, height, width) = video.dim();
+
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(num_frames as u32, 0)
+        .map_err(io::Error::other)?;
+    encoder
+        .set_frame_delay(frame_delay_ms, 1000)
+        .map_err(io::Error::other)?;
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    for frame in frames {
+        writer
+            .write_image_data(frame.as_raw())
+            .map_err(io::Error::other)?;
+    }
+    writer.finish().map_err(io::Error::other)
+}
+
+/// BT.601 limited range (Y, Cb, Cr) of an 8-bit RGB pixel.
+fn rgb_to_yuv([r, g, b]: [u8; 3]) -> (f64, f64, f64) {
+    let (r, g, b) = (r as f64 / 255., g as f64 / 255., b as f64 / 255.);
+    (
+        16. + 65.481 * r + 128.553 * g + 24.966 * b,
+        128. - 37.797 * r - 74.203 * g + 112. * b,
+        128. + 112. * r - 93.786 * g - 18.214 * b,
+    )
+}
+
+/// Writes a (frames, 3, size, size) video as YUV4MPEG2, played back at one
+/// frame every `frame_delay_ms` milliseconds. Chroma is averaged over 2x2
+/// blocks, rounding odd sizes up.
+pub fn write_y4m<W: Write>(writer: W, video: &Array4<f64>, frame_delay_ms: u16) -> io::Result<()> {
+    let frames = video_frames(video)?;
+    let (_, _, height, width) = video.dim();
+    let (chroma_height, chroma_width) = (height.div_ceil(2), width.div_ceil(2));
+
+    let mut writer = writer;
+    writeln!(
+        writer,
+        "YUV4MPEG2 W{width} H{height} F1000:{} Ip A1:1 C420jpeg",
+        frame_delay_ms.max(1)
+    )?;
+
+    let mut luma = vec![0u8; width * height];
+    let mut cb = vec![0u8; chroma_width * chroma_height];
+    let mut cr = vec![0u8; chroma_width * chroma_height];
+    for frame in frames {
+        // (Cb, Cr, pixels) summed over each 2x2 block
+        let mut chroma_sums = vec![(0., 0., 0); chroma_width * chroma_height];
+        for (x, y, pixel) in frame.enumerate_pixels() {
+            let (x, y) = (x as usize, y as usize);
+            let (l, u, v) = rgb_to_yuv(pixel.0);
+            luma[y * width + x] = l.round() as u8;
+
+            let sums = &mut chroma_sums[(y / 2) * chroma_width + x / 2];
+            sums.0 += u;
+            sums.1 += v;
+            sums.2 += 1;
+        }
+        for (c, &(u, v, count)) in chroma_sums.iter().enumerate() {
+            cb[c] = (u / count as f64).round() as u8;
+            cr[c] = (v / count as f64).round() as u8;
+        }
+
+        writer.write_all(b"FRAME\n")?;
+        writer.write_all(&luma)?;
+        writer.write_all(&cb)?;
+        writer.write_all(&cr)?;
+    }
+    writer.flush()
+}
+
+/// Writes a (frames, 3, size, size) video as a headerless stream of
+/// interleaved 8-bit RGB frames, as read by `ffmpeg -f rawvideo -pix_fmt
+/// rgb24`.
+pub fn write_raw<W: Write>(mut writer: W, video: &Array4<f64>) -> io::Result<()> {
+    for frame in video_frames(video)? {
+        writer.write_all(frame.as_raw())?;
+    }
+    writer.flush()
+}
+
+/// Saves a video, picking the format from the extension of `path`.
+pub fn save_video(
+    path: impl AsRef<Path>,
+    video: &Array4<f64>,
+    frame_delay_ms: u16,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let format = VideoFormat::from_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown video format for {}", path.display()),
+        )
+    })?;
+    check_video(video)?;
+
+    let writer = BufWriter::new(File::create(path)?);
+    match format {
+        VideoFormat::Gif => write_gif(writer, video, frame_delay_ms),
+        VideoFormat::Apng => write_apng(writer, video, frame_delay_ms),
+        VideoFormat::Y4m => write_y4m(writer, video, frame_delay_ms),
+        VideoFormat::Raw => write_raw(writer, video),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2x2 video of a white frame followed by a black one.
+    fn white_then_black() -> Array4<f64> {
+        let mut video = Array4::zeros((2, 3, 2, 2));
+        video.index_axis_mut(ndarray::Axis(0), 0).fill(1.);
+        video
+    }
+
+    #[test]
+    fn gif_frames_loop_with_the_delay() {
+        use image::codecs::gif::GifDecoder;
+        use image::AnimationDecoder;
+
+        let mut output = Vec::new();
+        write_gif(&mut output, &white_then_black(), 40).unwrap();
+
+        // looping is a NETSCAPE2.0 extension with a loop count of 0
+        let netscape = b"NETSCAPE2.0\x03\x01\x00\x00";
+        assert!(output.windows(netscape.len()).any(|w| w == netscape));
+
+        let frames = GifDecoder::new(output.as_slice())
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+        for (frame, value) in frames.iter().zip([255, 0]) {
+            assert_eq!(frame.delay().numer_denom_ms(), (40, 1));
+            assert!(frame
+                .buffer()
+                .pixels()
+                .all(|p| p.0 == [value, value, value, 255]));
+        }
+    }
+
+    #[test]
+    fn apng_frames_loop_with_the_delay() {
+        let mut output = Vec::new();
+        write_apng(&mut output, &white_then_black(), 40).unwrap();
+
+        let mut reader = png::Decoder::new(output.as_slice()).read_info().unwrap();
+        let animation = reader.info().animation_control.unwrap();
+        assert_eq!((animation.num_frames, animation.num_plays), (2, 0));
+
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        for value in [255, 0] {
+            reader.next_frame(&mut buffer).unwrap();
+            let control = reader.info().frame_control.unwrap();
+            assert_eq!((control.delay_num, control.delay_den), (40, 1000));
+            assert!(buffer[..2 * 2 * 3].iter().all(|&b| b == value));
+        }
+    }
+
+    #[test]
+    fn y4m_frames_are_yuv420() {
+        let mut output = Vec::new();
+        write_y4m(&mut output, &white_then_black(), 40).unwrap();
+
+        let header = b"YUV4MPEG2 W2 H2 F1000:40 Ip A1:1 C420jpeg\n";
+        assert_eq!(&output[..header.len()], header);
+        // four luma samples and one of each chroma per frame, with white at
+        // luma 235, black at 16 and both at neutral chroma 128
+        let frames: Vec<&[u8]> = output[header.len()..].chunks(6 + 4 + 1 + 1).collect();
+        assert_eq!(
+            frames,
+            [
+                b"FRAME\n\xeb\xeb\xeb\xeb\x80\x80",
+                b"FRAME\n\x10\x10\x10\x10\x80\x80"
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_frames_are_interleaved_rgb() {
+        let mut output = Vec::new();
+        write_raw(&mut output, &white_then_black()).unwrap();
+
+        assert_eq!(output.len(), 2 * 2 * 2 * 3);
+        assert!(output[..12].iter().all(|&b| b == 255));
+        assert!(output[12..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn malformed_videos_are_rejected() {
+        for shape in [(2, 3, 4, 6), (2, 1, 4, 4)] {
+            let video = Array4::zeros(shape);
+            let error = write_raw(Vec::new(), &video).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,468 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Serialize, Serializer};
+use std::str::FromStr;
+
+use super::caption::Captioner;
+use super::dataset::Dataset;
+use super::entry::Entry;
+use super::placement::shape_center;
+use super::shape::Shape;
+
+/// Kinds of questions a `QuestionGenerator` asks, after Sort-of-CLEVR.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuestionType {
+    /// How many shapes of a color, a type or both there are.
+    Count,
+    /// The color of the only shape of a type.
+    QueryColor,
+    /// The type of the only shape of a color.
+    QueryShape,
+    /// The type of the shape closest to a given one.
+    Closest,
+    /// The type of the shape furthest from a given one.
+    Furthest,
+    /// Whether one shape is further left than another, by their centers.
+    LeftOf,
+}
+
+impl QuestionType {
+    pub fn all() -> Vec<QuestionType> {
+        vec![
+            QuestionType::Count,
+            QuestionType::QueryColor,
+            QuestionType::QueryShape,
+            QuestionType::Closest,
+            QuestionType::Furthest,
+            QuestionType::LeftOf,
+        ]
+    }
+
+    /// Name of the question type, as `FromStr` parses it.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            QuestionType::Count => "count",
+            QuestionType::QueryColor => "query_color",
+            QuestionType::QueryShape => "query_shape",
+            QuestionType::Closest => "closest",
+            QuestionType::Furthest => "furthest",
+            QuestionType::LeftOf => "left_of",
+        }
+    }
+}
+
+impl Serialize for QuestionType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl FromStr for QuestionType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        QuestionType::all()
+            .into_iter()
+            .find(|question_type| question_type.as_str() == s)
+            .ok_or_else(|| format!("Unknown question type '{s}'"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Question {
+    pub question_type: QuestionType,
+    pub question: String,
+    pub answer: String,
+}
+
+/// Generates questions about entries with short answers, from a vocabulary
+/// fixed by the dataset: "yes", "no", counts, color names and shape names,
+/// which may be several words such as "digit 3" or "sprite 0". Shapes are
+/// referred to by color and type, so only shapes unique in their scene by
+/// both are asked about.
+#[derive(Debug, Clone)]
+pub struct QuestionGenerator {
+    question_types: Vec<QuestionType>,
+    names: Captioner,
+    max_count: usize,
+    balanced: bool,
+}
+
+fn distance(a: &Shape, b: &Shape) -> f64 {
+    let ((ax, ay), (bx, by)) = (shape_center(a), shape_center(b));
+    (ax - bx).hypot(ay - by)
+}
+
+impl QuestionGenerator {
+    pub fn new() -> Self {
+        QuestionGenerator {
+            question_types: QuestionType::all(),
+            names: Captioner::new(),
+            max_count: 10,
+            balanced: true,
+        }
+    }
+
+    pub fn question_types(mut self, question_types: Vec<QuestionType>) -> Self {
+        if question_types.is_empty() {
+            panic!("Question types must not be empty!");
+        }
+
+        self.question_types = question_types;
+        self
+    }
+
+    /// Names colors and shapes as `captioner` does.
+    pub fn names(mut self, captioner: Captioner) -> Self {
+        self.names = captioner;
+        self
+    }
+
+    /// Largest count answered. Count questions with larger answers are
+    /// never asked.
+    pub fn max_count(mut self, max_count: usize) -> Self {
+        self.max_count = max_count;
+        self
+    }
+
+    /// Whether to sample question types uniformly, and then answers
+    /// uniformly within each type, rather than questions uniformly. This
+    /// keeps common answers such as "no" or "0" from dominating.
+    pub fn balanced(mut self, balanced: bool) -> Self {
+        self.balanced = balanced;
+        self
+    }
+
+    fn color_names(&self, dataset: &Dataset) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for color in dataset.get_colors() {
+            let name = self.names.describe_color(color);
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Singular and plural shape names.
+    fn shape_names(&self, dataset: &Dataset) -> Vec<(String, String)> {
+        let mut names: Vec<(String, String)> = Vec::new();
+        for &shape_type in dataset.get_shape_types() {
+            let name = self.names.describe_shape_type(shape_type);
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Every answer questions about `dataset` can have.
+    pub fn vocabulary(&self, dataset: &Dataset) -> Vec<String> {
+        let mut vocabulary = vec!["yes".to_string(), "no".to_string()];
+        vocabulary.extend((0..=self.max_count).map(|count| count.to_string()));
+        vocabulary.extend(self.color_names(dataset));
+        vocabulary.extend(self.shape_names(dataset).into_iter().map(|(s, _)| s));
+        vocabulary
+    }
+
+    /// Every question this generator can ask about `entry`.
+    pub fn all_questions(&self, dataset: &Dataset, entry: &Entry) -> Vec<Question> {
+        let shapes = entry.shapes();
+        let described: Vec<(String, String)> = shapes
+            .iter()
+            .map(|shape| {
+                (
+                    self.names.describe_color(shape.color),
+                    self.names.describe_shape_type(shape.shape_type).0,
+                )
+            })
+            .collect();
+        let count =
+            |f: &dyn Fn(&(String, String)) -> bool| described.iter().filter(|d| f(d)).count();
+        // shapes unique in the scene by color and type, as "<color> <shape>"
+        let unique: Vec<(usize, String)> = described
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| count(&|other| other == *d) == 1)
+            .map(|(i, (color, shape))| (i, format!("{color} {shape}")))
+            .collect();
+
+        let mut questions = Vec::new();
+        let mut ask = |question_type, question: String, answer: String| {
+            questions.push(Question {
+                question_type,
+                question,
+                answer,
+            })
+        };
+
+        for &question_type in &self.question_types {
+            match question_type {
+                QuestionType::Count => {
+                    let colors = self.color_names(dataset);
+                    let shape_names = self.shape_names(dataset);
+                    let mut counts = Vec::new();
+                    for (singular, plural) in &shape_names {
+                        counts.push((
+                            format!("How many {plural} are there?"),
+                            count(&|d| d.1 == *singular),
+                        ));
+                    }
+                    for color in &colors {
+                        counts.push((
+                            format!("How many {color} shapes are there?"),
+                            count(&|d| d.0 == *color),
+                        ));
+                        for (singular, plural) in &shape_names {
+                            counts.push((
+                                format!("How many {color} {plural} are there?"),
+                                count(&|d| d.0 == *color && d.1 == *singular),
+                            ));
+                        }
+                    }
+                    for (question, n) in counts {
+                        if n <= self.max_count {
+                            ask(question_type, question, n.to_string());
+                        }
+                    }
+                }
+                QuestionType::QueryColor => {
+                    for (color, shape) in &described {
+                        if count(&|d| d.1 == *shape) == 1 {
+                            ask(
+                                question_type,
+                                format!("What color is the {shape}?"),
+                                color.clone(),
+                            );
+                        }
+                    }
+                }
+                QuestionType::QueryShape => {
+                    for (color, shape) in &described {
+                        if count(&|d| d.0 == *color) == 1 {
+                            ask(
+                                question_type,
+                                format!("What shape is the {color} object?"),
+                                shape.clone(),
+                            );
+                        }
+                    }
+                }
+                QuestionType::Closest | QuestionType::Furthest => {
+                    for (i, name) in &unique {
+                        let others = (0..shapes.len()).filter(|j| j != i);
+                        let by_distance = |&a: &usize, &b: &usize| {
+                            distance(&shapes[*i], &shapes[a])
+                                .total_cmp(&distance(&shapes[*i], &shapes[b]))
+                        };
+                        let (found, word) = match question_type {
+                            QuestionType::Closest => (others.min_by(by_distance), "closest to"),
+                            _ => (others.max_by(by_distance), "furthest from"),
+                        };
+                        if let Some(j) = found {
+                            ask(
+                                question_type,
+                                format!("What shape is the object {word} the {name}?"),
+                                described[j].1.clone(),
+                            );
+                        }
+                    }
+                }
+                QuestionType::LeftOf => {
+                    for (i, a) in &unique {
+                        for (j, b) in unique.iter().filter(|(j, _)| j != i) {
+                            let left = shape_center(&shapes[*i]).0 < shape_center(&shapes[*j]).0;
+                            ask(
+                                question_type,
+                                format!("Is the {a} left of the {b}?"),
+                                if left { "yes" } else { "no" }.to_string(),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        questions
+    }
+
+    /// Up to `num_questions` distinct questions about `entry`, sampled as
+    /// configured with `balanced`.
+    pub fn generate(
+        &self,
+        dataset: &Dataset,
+        entry: &Entry,
+        num_questions: usize,
+    ) -> Vec<Question> {
+        let mut rng = rand::thread_rng();
+        let mut candidates = self.all_questions(dataset, entry);
+        let mut questions = Vec::with_capacity(num_questions);
+
+        while questions.len() < num_questions && !candidates.is_empty() {
+            let index = if self.balanced {
+                let mut types: Vec<QuestionType> = Vec::new();
+                for question in &candidates {
+                    if !types.contains(&question.question_type) {
+                        types.push(question.question_type);
+                    }
+                }
+                let question_type = *types.choose(&mut rng).expect("Candidates are not empty");
+
+                let mut answers: Vec<&str> = Vec::new();
+                for question in candidates
+                    .iter()
+                    .filter(|q| q.question_type == question_type)
+                {
+                    if !answers.contains(&question.answer.as_str()) {
+                        answers.push(&question.answer);
+                    }
+                }
+                let answer = *answers
+                    .choose(&mut rng)
+                    .expect("Question type has candidates");
+
+                let matching: Vec<usize> = (0..candidates.len())
+                    .filter(|&i| {
+                        candidates[i].question_type == question_type
+                            && candidates[i].answer == answer
+                    })
+                    .collect();
+                *matching.choose(&mut rng).expect("Answer has candidates")
+            } else {
+                rng.gen_range(0..candidates.len())
+            };
+            questions.push(candidates.swap_remove(index));
+        }
+        questions
+    }
+}
+
+impl Default for QuestionGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Color, ShapeType};
+
+    #[test]
+    fn answers_are_in_the_vocabulary() {
+        let dataset = Dataset::new()
+            .shape_types(vec![ShapeType::Square, ShapeType::Circle])
+            .add_color(255, 0, 0)
+            .add_color(0, 0, 255)
+            .size(0.1)
+            .num_shapes_range(1, 4);
+        let generator = QuestionGenerator::new();
+        let vocabulary = generator.vocabulary(&dataset);
+
+        for _ in 0..20 {
+            let entry = dataset.generate_random_entry();
+            for question in generator.all_questions(&dataset, &entry) {
+                assert!(vocabulary.contains(&question.answer), "{question:?}");
+            }
+        }
+    }
+
+    /// A red square, a blue circle and a red circle in a row, and a dataset
+    /// of red and blue squares and circles.
+    fn scene() -> (Dataset, Entry) {
+        let shapes = [
+            (ShapeType::Square, Color(255, 0, 0), 0.1),
+            (ShapeType::Circle, Color(0, 0, 255), 0.3),
+            (ShapeType::Circle, Color(255, 0, 0), 0.8),
+        ]
+        .into_iter()
+        .map(|(shape_type, color, x)| {
+            let mut shape = Shape::new(&shape_type).size(0.1).position(x, 0.5);
+            shape.color = color;
+            shape
+        })
+        .collect();
+        let dataset = Dataset::new()
+            .shape_types(vec![ShapeType::Square, ShapeType::Circle])
+            .add_color(255, 0, 0)
+            .add_color(0, 0, 255);
+        (dataset, Entry::new(shapes))
+    }
+
+    #[test]
+    fn relational_answers_follow_geometry() {
+        let (dataset, entry) = scene();
+        let questions = QuestionGenerator::new().all_questions(&dataset, &entry);
+        let answer = |question: &str| {
+            questions
+                .iter()
+                .find(|q| q.question == question)
+                .map(|q| q.answer.as_str())
+        };
+
+        assert_eq!(answer("How many red shapes are there?"), Some("2"));
+        assert_eq!(answer("What color is the square?"), Some("red"));
+        assert_eq!(answer("What shape is the blue object?"), Some("circle"));
+        assert_eq!(
+            answer("What shape is the object closest to the red square?"),
+            Some("circle")
+        );
+        assert_eq!(
+            answer("What shape is the object furthest from the blue circle?"),
+            Some("circle")
+        );
+        assert_eq!(
+            answer("Is the red circle left of the blue circle?"),
+            Some("no")
+        );
+        assert_eq!(answer("What color is the circle?"), None);
+    }
+
+    #[test]
+    fn balanced_sampling_evens_out_types_and_answers() {
+        // 8 count questions, answered "0" once, "1" five times and "2"
+        // twice, against a single query color question
+        let (dataset, entry) = scene();
+        let generator = QuestionGenerator::new()
+            .question_types(vec![QuestionType::Count, QuestionType::QueryColor]);
+        let fractions = |generator: &QuestionGenerator| {
+            let samples = 3000;
+            let mut counts = [0; 4];
+            for _ in 0..samples {
+                let question = &generator.generate(&dataset, &entry, 1)[0];
+                let index = match question.answer.as_str() {
+                    "0" => 0,
+                    "1" => 1,
+                    "2" => 2,
+                    _ => 3,
+                };
+                counts[index] += 1;
+            }
+            counts.map(|count| count as f64 / samples as f64)
+        };
+        let close = |actual: [f64; 4], expected: [f64; 4]| {
+            actual
+                .iter()
+                .zip(expected)
+                .all(|(a, e)| (a - e).abs() < 0.05)
+        };
+
+        let balanced = fractions(&generator);
+        assert!(
+            close(balanced, [1. / 6., 1. / 6., 1. / 6., 0.5]),
+            "{balanced:?}"
+        );
+        let uniform = fractions(&generator.balanced(false));
+        assert!(
+            close(uniform, [1. / 9., 5. / 9., 2. / 9., 1. / 9.]),
+            "{uniform:?}"
+        );
+    }
+
+    #[test]
+    fn question_types_round_trip_through_names() {
+        for question_type in QuestionType::all() {
+            assert_eq!(question_type.as_str().parse(), Ok(question_type));
+        }
+        assert!("color".parse::<QuestionType>().is_err());
+    }
+}